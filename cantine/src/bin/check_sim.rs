@@ -1,16 +1,22 @@
 use std::{
+    collections::hash_map::DefaultHasher,
     convert::TryFrom,
     env,
+    hash::{Hash, Hasher},
     path::Path,
+    str::FromStr,
     sync::{mpsc, Arc},
     thread::spawn,
 };
 
 use crossbeam_channel;
-use tantivy::{schema::Term, Index, Result};
+use serde::Serialize;
+use serde_json;
+use tantivy::{query::Query, schema::Term, Index, Result};
 
 use cantine::{
     database::DatabaseReader,
+    flags::flag_from_env,
     index::RecipeIndex,
     model::{Recipe, RecipeId, Sort},
 };
@@ -23,48 +29,249 @@ struct Checked {
     len: usize,
     simple: Res,
     weighted: Res,
+    dismax: Res,
 }
 
 struct Res {
     num_found: usize,
-    recall: f32,
+    /// None when the recipe has no known similar recipes
+    recall: Option<f32>,
     position: Option<usize>,
 }
 
+#[derive(Serialize, Debug, Clone)]
+struct CheckOptions {
+    num_workers: usize,
+    num_keywords: usize,
+    num_results: usize,
+    min_doc_freq: u64,
+    min_term_length: usize,
+    ignore_suffixes: Vec<String>,
+    sample_size: Option<usize>,
+    sample_seed: u64,
+    name_weight: f32,
+    ingredients_weight: f32,
+    instructions_weight: f32,
+    normalize_length: bool,
+    dismax_tiebreaker: f32,
+}
+
+impl CheckOptions {
+    /// I haven't put any effort in the tokenization step, so there's
+    /// plenty of "relevant rubbish" in the index like "100g", "tbsp",
+    /// unicode fractions, etc. These heuristics are just an attempt of
+    /// reducing the garbage, but a decent injection pipeline should be
+    /// getting rid of these, not an ad-hoc filter
+    fn accepts(&self, term: &Term, doc_freq: u64) -> bool {
+        let text = term.text();
+        doc_freq >= self.min_doc_freq
+            && text.chars().count() >= self.min_term_length
+            && !self
+                .ignore_suffixes
+                .iter()
+                .any(|suffix| text.ends_with(suffix.as_str()))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Summary {
+    num_checked: usize,
+    /// Mean over the recipes that have known similar recipes
+    mean_recall: f32,
+    mean_found: f32,
+    /// How many times the recipe itself was found at each position
+    positions: Vec<usize>,
+    /// How many times the recipe itself was not found at all
+    not_found: usize,
+
+    #[serde(skip)]
+    num_recalled: usize,
+}
+
+impl Summary {
+    fn new(num_results: usize) -> Self {
+        Self {
+            num_checked: 0,
+            mean_recall: 0.0,
+            mean_found: 0.0,
+            positions: vec![0; num_results],
+            not_found: 0,
+            num_recalled: 0,
+        }
+    }
+
+    fn add(&mut self, res: &Res) {
+        self.num_checked += 1;
+        self.mean_found += (res.num_found as f32 - self.mean_found) / self.num_checked as f32;
+
+        if let Some(recall) = res.recall {
+            self.num_recalled += 1;
+            self.mean_recall += (recall - self.mean_recall) / self.num_recalled as f32;
+        }
+
+        match res.position {
+            Some(pos) => self.positions[pos] += 1,
+            None => self.not_found += 1,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    options: CheckOptions,
+    simple: Summary,
+    weighted: Summary,
+    dismax: Summary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format: {}", s)),
+        }
+    }
+}
+
+/// A random-looking, but reproducible, subset of the recipe ids
+fn sample(database: &DatabaseReader<Recipe>, size: usize, seed: u64) -> Vec<RecipeId> {
+    let mut ids = database
+        .ids()
+        .map(|&id| {
+            let mut hasher = DefaultHasher::new();
+            (seed, id).hash(&mut hasher);
+            (hasher.finish(), id)
+        })
+        .collect::<Vec<_>>();
+
+    ids.sort_unstable();
+    ids.into_iter().take(size).map(|(_hash, id)| id).collect()
+}
+
+fn print_summary(name: &str, summary: &Summary) {
+    eprintln!(
+        "{}: checked={} mean_recall={:.4} mean_found={:.2} not_found={} positions={:?}",
+        name,
+        summary.num_checked,
+        summary.mean_recall,
+        summary.mean_found,
+        summary.not_found,
+        summary.positions
+    );
+}
+
+const NUM_WORKERS: &str = "NUM_WORKERS";
+const NUM_KEYWORDS: &str = "NUM_KEYWORDS";
+const NUM_RESULTS: &str = "NUM_RESULTS";
+const MIN_DOC_FREQ: &str = "MIN_DOC_FREQ";
+const MIN_TERM_LENGTH: &str = "MIN_TERM_LENGTH";
+const IGNORE_SUFFIXES: &str = "IGNORE_SUFFIXES";
+const SAMPLE_SIZE: &str = "SAMPLE_SIZE";
+const SAMPLE_SEED: &str = "SAMPLE_SEED";
+const FORMAT: &str = "FORMAT";
+const NAME_WEIGHT: &str = "NAME_WEIGHT";
+const INGREDIENTS_WEIGHT: &str = "INGREDIENTS_WEIGHT";
+const INSTRUCTIONS_WEIGHT: &str = "INSTRUCTIONS_WEIGHT";
+const NORMALIZE_LENGTH: &str = "NORMALIZE_LENGTH";
+const DISMAX_TIEBREAKER: &str = "DISMAX_TIEBREAKER";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn get_f32_from_env_or(key: &str, default: f32) -> f32 {
+    env::var(key)
+        .ok()
+        .map(|v| f32::from_str(&v).expect("valid f32"))
+        .unwrap_or(default)
+}
+
 fn main() -> Result<()> {
     let base_dir = env::args()
         .nth(1)
         .expect("First parameter is a path to a directory");
 
+    let ignore_suffixes = env::var(IGNORE_SUFFIXES)
+        .unwrap_or_else(|_| "tbsp".to_string())
+        .split(',')
+        .filter(|suffix| !suffix.is_empty())
+        .map(String::from)
+        .collect();
+
+    let options = CheckOptions {
+        num_workers: get_usize_from_env_or(NUM_WORKERS, 4),
+        num_keywords: get_usize_from_env_or(NUM_KEYWORDS, 20),
+        num_results: get_usize_from_env_or(NUM_RESULTS, 11),
+        min_doc_freq: get_usize_from_env_or(MIN_DOC_FREQ, 6) as u64,
+        min_term_length: get_usize_from_env_or(MIN_TERM_LENGTH, 5),
+        ignore_suffixes,
+        sample_size: env::var(SAMPLE_SIZE)
+            .ok()
+            .map(|v| usize::from_str(&v).expect("valid usize")),
+        sample_seed: get_usize_from_env_or(SAMPLE_SEED, 0) as u64,
+        name_weight: get_f32_from_env_or(NAME_WEIGHT, 1.0),
+        ingredients_weight: get_f32_from_env_or(INGREDIENTS_WEIGHT, 1.0),
+        instructions_weight: get_f32_from_env_or(INSTRUCTIONS_WEIGHT, 1.0),
+        normalize_length: flag_from_env(NORMALIZE_LENGTH),
+        dismax_tiebreaker: get_f32_from_env_or(DISMAX_TIEBREAKER, 0.1),
+    };
+
+    let format = env::var(FORMAT)
+        .ok()
+        .map(|v| Format::from_str(&v).expect("valid format (csv or json)"))
+        .unwrap_or(Format::Csv);
+
     let base_path = Path::new(&base_dir);
     let index_path = base_path.join("tantivy");
     let db_path = base_path.join("database");
 
     let index = Index::open_in_dir(&index_path)?;
-    let reader = index.reader()?;
+    // Every worker holds on to a searcher for as long as it runs
+    let reader = index
+        .reader_builder()
+        .num_searchers(options.num_workers)
+        .try_into()?;
 
     let recipe_index = Arc::new(RecipeIndex::try_from(&index.schema())?);
     let database = Arc::new(DatabaseReader::<Recipe>::open(&db_path)?);
-    let topterms = Arc::new(TopTerms::new(
+    let mut topterms = TopTerms::new(
         &index,
         vec![
             recipe_index.name,
             recipe_index.ingredients,
             recipe_index.instructions,
         ],
-    )?);
+    )?;
+    topterms.set_weight(recipe_index.name, options.name_weight);
+    topterms.set_weight(recipe_index.ingredients, options.ingredients_weight);
+    topterms.set_weight(recipe_index.instructions, options.instructions_weight);
+    topterms.set_length_normalization(options.normalize_length);
+    let topterms = Arc::new(topterms);
 
     let (id_sender, id_receiver) = crossbeam_channel::unbounded();
     let (checked_sender, checked_receiver) = mpsc::channel();
 
     let mut workers = Vec::new();
-    for _ in 0..4 {
+    for _ in 0..options.num_workers {
         let receiver = id_receiver.clone();
         let database = database.clone();
         let recipe_index = recipe_index.clone();
         let topterms = topterms.clone();
         let searcher = reader.searcher();
         let checked_sender = checked_sender.clone();
+        let options = options.clone();
 
         workers.push(spawn(move || -> Result<()> {
             for id in receiver {
@@ -76,27 +283,18 @@ fn main() -> Result<()> {
 
                 let mut input = Vec::new();
 
-                input.push(recipe.name.as_str());
+                input.push((recipe_index.name, recipe.name.as_str()));
                 for ingredient in &recipe.ingredients {
-                    input.push(ingredient.as_str());
+                    input.push((recipe_index.ingredients, ingredient.as_str()));
                 }
                 for instruction in &recipe.instructions {
-                    input.push(instruction.as_str());
+                    input.push((recipe_index.instructions, instruction.as_str()));
                 }
 
-                let keywords = topterms.extract_filtered(
-                    20,
-                    input.join("\n").as_str(),
-                    &|term: &Term, _tf, doc_freq, _num_docs| {
-                        // I haven't put any effort in the tokenization step,
-                        // so there's plenty of "relevant rubbish" in the
-                        // index like "100g", "tbsp", unicode fractions, etc.
-                        // These heuristics are just an attempt of reducing the
-                        // garbage, but a decent injection pipeline should be getting
-                        // rid of these, not an ad-hoc filter
-                        let text = term.text();
-                        doc_freq > 5 && text.chars().count() > 4 && !text.ends_with("tbsp")
-                    },
+                let keywords = topterms.extract_filtered_from_fields(
+                    options.num_keywords,
+                    &input,
+                    &|term: &Term, _tf, doc_freq, _num_docs| options.accepts(term, doc_freq),
                 );
 
                 let top_pretty = keywords
@@ -109,9 +307,14 @@ fn main() -> Result<()> {
                 let canon_sim_ids = recipe.similar_recipe_ids;
                 let src_sim_len = canon_sim_ids.len() as f32;
 
-                let recallfn = |query| -> Result<Res> {
-                    let (_num_matching, similar_ids, _after) =
-                        recipe_index.search(&searcher, &query, 11, Sort::Relevance, None)?;
+                let recallfn = |query: &dyn Query| -> Result<Res> {
+                    let (_num_matching, similar_ids, _after) = recipe_index.search(
+                        &searcher,
+                        query,
+                        options.num_results,
+                        Sort::Relevance,
+                        None,
+                    )?;
 
                     let position = similar_ids.iter().position(|&sim_id| recipe_id == sim_id);
 
@@ -124,14 +327,19 @@ fn main() -> Result<()> {
 
                     Ok(Res {
                         num_found: similar_ids.len(),
-                        recall: hit as f32 / src_sim_len,
+                        recall: if canon_sim_ids.is_empty() {
+                            None
+                        } else {
+                            Some(hit as f32 / src_sim_len)
+                        },
                         position,
                     })
                 };
 
                 let len = keywords.len();
-                let simple = recallfn(keywords.clone().into_query())?;
-                let weighted = recallfn(keywords.into_boosted_query(1.0))?;
+                let simple = recallfn(&keywords.clone().into_query())?;
+                let weighted = recallfn(&keywords.clone().into_boosted_query(1.0))?;
+                let dismax = recallfn(&keywords.into_dismax_query(1.0, options.dismax_tiebreaker))?;
 
                 let checked = Checked {
                     id: recipe_id,
@@ -139,6 +347,7 @@ fn main() -> Result<()> {
                     top_pretty,
                     simple,
                     weighted,
+                    dismax,
                 };
 
                 checked_sender.send(checked).expect("send() always works");
@@ -150,29 +359,69 @@ fn main() -> Result<()> {
     drop(id_receiver);
     drop(checked_sender);
 
-    for &id in database.ids() {
-        id_sender.send(id).expect("send() always works");
+    if let Some(size) = options.sample_size {
+        for id in sample(&database, size, options.sample_seed) {
+            id_sender.send(id).expect("send() always works");
+        }
+    } else {
+        for &id in database.ids() {
+            id_sender.send(id).expect("send() always works");
+        }
     }
     drop(id_sender);
 
-    println!("recipe_id,len,top5,simple_weighted_found_delta,simple_pos,weighted_pos,simple_recall,weighted_recall");
+    let mut simple = Summary::new(options.num_results);
+    let mut weighted = Summary::new(options.num_results);
+    let mut dismax = Summary::new(options.num_results);
+
+    if format == Format::Csv {
+        println!("recipe_id,len,top5,simple_weighted_found_delta,simple_pos,weighted_pos,simple_recall,weighted_recall,dismax_pos,dismax_recall");
+    }
     for checked in checked_receiver {
-        println!(
-            "{},{},{},{},{},{},{},{}",
-            checked.id,
-            checked.len,
-            checked.top_pretty.join(";"),
-            checked.simple.num_found - checked.weighted.num_found,
-            checked.simple.position.map(|p| p as isize).unwrap_or(-1),
-            checked.weighted.position.map(|p| p as isize).unwrap_or(-1),
-            checked.simple.recall,
-            checked.weighted.recall,
-        );
+        simple.add(&checked.simple);
+        weighted.add(&checked.weighted);
+        dismax.add(&checked.dismax);
+
+        if format == Format::Csv {
+            println!(
+                "{},{},{},{},{},{},{},{},{},{}",
+                checked.id,
+                checked.len,
+                checked.top_pretty.join(";"),
+                checked.simple.num_found as isize - checked.weighted.num_found as isize,
+                checked.simple.position.map(|p| p as isize).unwrap_or(-1),
+                checked.weighted.position.map(|p| p as isize).unwrap_or(-1),
+                checked.simple.recall.unwrap_or(f32::NAN),
+                checked.weighted.recall.unwrap_or(f32::NAN),
+                checked.dismax.position.map(|p| p as isize).unwrap_or(-1),
+                checked.dismax.recall.unwrap_or(f32::NAN),
+            );
+        }
     }
 
     for worker in workers.into_iter() {
         worker.join().expect("join() always works")?;
     }
 
+    match format {
+        Format::Csv => {
+            print_summary("simple", &simple);
+            print_summary("weighted", &weighted);
+            print_summary("dismax", &dismax);
+        }
+        Format::Json => {
+            let report = Report {
+                options,
+                simple,
+                weighted,
+                dismax,
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("serializable report")
+            );
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+use env_logger;
+use serde::Deserialize;
+use serde_json::{self, Value};
+use tantivy::{Index, Result};
+use uuid::Uuid;
+
+use cantine::{
+    database::DatabaseReader,
+    model::{Recipe, RecipeId, SearchQuery},
+    search::{SearchConfig, SearchState},
+};
+
+/// A line of the judgments file: a search and how relevant some
+/// recipes are for it. Zero means irrelevant, higher is better
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Judgment {
+    query: Value,
+    relevant: HashMap<Uuid, u32>,
+}
+
+struct Judged {
+    label: String,
+    query: Value,
+    grades: HashMap<RecipeId, u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Metrics {
+    ndcg: f64,
+    mrr: f64,
+    recall: f64,
+}
+
+impl Metrics {
+    fn new(found: &[RecipeId], grades: &HashMap<RecipeId, u32>, k: usize) -> Self {
+        let found = &found[..found.len().min(k)];
+        let grade = |id: &RecipeId| grades.get(id).copied().unwrap_or(0);
+        let gain =
+            |pos: usize, grade: u32| (2f64.powi(grade as i32) - 1.0) / (pos as f64 + 2.0).log2();
+
+        let dcg: f64 = found
+            .iter()
+            .enumerate()
+            .map(|(pos, id)| gain(pos, grade(id)))
+            .sum();
+
+        let mut ideal = grades.values().copied().collect::<Vec<_>>();
+        ideal.sort_by(|a, b| b.cmp(a));
+        let idcg: f64 = ideal
+            .into_iter()
+            .take(k)
+            .enumerate()
+            .map(|(pos, grade)| gain(pos, grade))
+            .sum();
+
+        let mrr = found
+            .iter()
+            .position(|id| grade(id) > 0)
+            .map_or(0.0, |pos| 1.0 / (pos as f64 + 1.0));
+
+        let num_relevant = grades.values().filter(|&&grade| grade > 0).count();
+        let num_found = found.iter().filter(|id| grade(id) > 0).count();
+
+        Self {
+            ndcg: dcg / idcg,
+            mrr,
+            recall: num_found as f64 / num_relevant as f64,
+        }
+    }
+
+    fn mean(all: &[Self]) -> Self {
+        let len = all.len() as f64;
+        Self {
+            ndcg: all.iter().map(|m| m.ndcg).sum::<f64>() / len,
+            mrr: all.iter().map(|m| m.mrr).sum::<f64>() / len,
+            recall: all.iter().map(|m| m.recall).sum::<f64>() / len,
+        }
+    }
+}
+
+fn read_judgments(path: &Path, database: &DatabaseReader<Recipe>) -> Result<Vec<Judged>> {
+    let mut judged = Vec::new();
+
+    for (line_number, line) in BufReader::new(fs::File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let judgment: Judgment = serde_json::from_str(&line).map_err(invalid_data)?;
+        // Fail early instead of in the middle of the evaluation
+        let query: SearchQuery =
+            serde_json::from_value(judgment.query.clone()).map_err(invalid_data)?;
+
+        let label = query
+            .fulltext
+            .unwrap_or_else(|| format!("line {}", line_number + 1));
+
+        let mut grades = HashMap::with_capacity(judgment.relevant.len());
+        for (uuid, grade) in judgment.relevant {
+            if let Some(&id) = database.id_for_uuid(&uuid) {
+                grades.insert(id, grade);
+            } else {
+                log::warn!("Ignoring unknown recipe {} for '{}'", uuid, label);
+            }
+        }
+
+        if grades.values().all(|&grade| grade == 0) {
+            log::warn!("Skipping '{}': no relevant recipe judged", label);
+            continue;
+        }
+
+        judged.push(Judged {
+            label,
+            query: judgment.query,
+            grades,
+        });
+    }
+
+    Ok(judged)
+}
+
+fn evaluate(state: &SearchState, judged: &[Judged], k: usize) -> Result<Vec<Metrics>> {
+    let mut metrics = Vec::with_capacity(judged.len());
+
+    for judgment in judged {
+        let mut query: SearchQuery =
+            serde_json::from_value(judgment.query.clone()).map_err(invalid_data)?;
+        query.num_items = Some(k as u8);
+        query.after = None;
+        query.agg = None;
+
+        let (_total, found, _after, _agg) = state.search(query, None)?;
+        metrics.push(Metrics::new(&found, &judgment.grades, k));
+    }
+
+    Ok(metrics)
+}
+
+fn invalid_data(err: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+const K: &str = "K";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter is a path to a directory");
+    let judgments_path = env::args()
+        .nth(2)
+        .expect("Second parameter is a path to a judgments file");
+
+    // Up to two configuration files to compare. Uses the default
+    // configuration if none is given
+    let mut configs = Vec::new();
+    for path in env::args().skip(3) {
+        configs.push((path.clone(), SearchConfig::load(&path)?));
+    }
+    if configs.is_empty() {
+        configs.push(("default".to_string(), SearchConfig::default()));
+    }
+    assert!(configs.len() <= 2, "Can only compare two configurations");
+
+    let k = env::var(K)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(10);
+    assert!(k > 0 && k <= 255, "K must be within [1, 255]");
+
+    let base_path = Path::new(&base_dir);
+    let index = Index::open_in_dir(base_path.join("tantivy"))?;
+    let database = DatabaseReader::<Recipe>::open(base_path.join("database"))?;
+
+    let judged = read_judgments(Path::new(&judgments_path), &database)?;
+    assert!(!judged.is_empty(), "No usable judgments found");
+
+    let mut results = Vec::with_capacity(configs.len());
+    for (_name, config) in configs.iter() {
+        let state = SearchState::new(&index, config, usize::MAX)?;
+        results.push(evaluate(&state, &judged, k)?);
+    }
+
+    println!("config,queries,ndcg@{},mrr,recall@{}", k, k);
+    for ((name, _config), metrics) in configs.iter().zip(results.iter()) {
+        let mean = Metrics::mean(metrics);
+        println!(
+            "{},{},{:.4},{:.4},{:.4}",
+            quoted(name),
+            metrics.len(),
+            mean.ndcg,
+            mean.mrr,
+            mean.recall
+        );
+    }
+    println!();
+
+    if let [first, second] = results.as_slice() {
+        // Only the queries that changed, biggest losses first
+        let mut diffs = judged
+            .iter()
+            .zip(first.iter().zip(second.iter()))
+            .filter(|(_judged, (a, b))| a != b)
+            .collect::<Vec<_>>();
+        diffs.sort_by(|(_, (a1, b1)), (_, (a2, b2))| {
+            (b1.ndcg - a1.ndcg)
+                .partial_cmp(&(b2.ndcg - a2.ndcg))
+                .expect("ndcg is never NaN")
+        });
+
+        println!("query,ndcg_delta,ndcg_a,ndcg_b,mrr_a,mrr_b,recall_a,recall_b");
+        for (judgment, (a, b)) in diffs {
+            println!(
+                "{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}",
+                quoted(&judgment.label),
+                b.ndcg - a.ndcg,
+                a.ndcg,
+                b.ndcg,
+                a.mrr,
+                b.mrr,
+                a.recall,
+                b.recall
+            );
+        }
+    } else {
+        println!("query,ndcg,mrr,recall");
+        for (judgment, metrics) in judged.iter().zip(results[0].iter()) {
+            println!(
+                "{},{:.4},{:.4},{:.4}",
+                quoted(&judgment.label),
+                metrics.ndcg,
+                metrics.mrr,
+                metrics.recall
+            );
+        }
+    }
+
+    Ok(())
+}
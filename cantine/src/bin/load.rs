@@ -1,20 +1,35 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
     env,
-    io::{self, BufRead},
-    path::Path,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
     str::FromStr,
-    sync::{mpsc::channel, Arc, RwLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::channel,
+        Arc, RwLock,
+    },
     thread::spawn,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+use bzip2::bufread::MultiBzDecoder;
 use crossbeam_channel::unbounded;
 use env_logger;
+use flate2::bufread::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
 use serde_json;
 
-use tantivy::{self, directory::MmapDirectory, schema::SchemaBuilder, Index, Result};
+use tantivy::{
+    self, directory::MmapDirectory, schema::SchemaBuilder, Document, Index, IndexWriter, Result,
+    TantivyError,
+};
 
-use cantine::database::DatabaseWriter;
+use cantine::database::{DatabaseReader, DatabaseWriter};
+use cantine::dedup::{Deduplicator, Signature};
+use cantine::flags::flag_from_env;
 use cantine::index::RecipeIndex;
 use cantine::model::Recipe;
 
@@ -27,8 +42,285 @@ pub struct LoadOptions {
     commit_every: usize,
     /// Number of worker threads to start
     num_producers: usize,
+    /// Seconds between progress reports
+    report_every: u64,
+    /// Continue an interrupted load instead of starting from scratch
+    resume: bool,
+    /// Write to the database and index in the same order as the input
+    preserve_order: bool,
+    /// What to do with duplicate recipes. Disables detection when None
+    dedup: Option<DuplicatePolicy>,
+    /// Minimum similarity for recipes to be considered near-duplicates
+    dedup_threshold: f32,
     /// Path to a non-existing directory
     output_dir: String,
+    /// Files to read recipes from. Reads from stdin when empty
+    inputs: Vec<PathBuf>,
+}
+
+/// Which input lines have made it into the database and index. Saved
+/// at every commit so that an interrupted load can be resumed
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+struct Checkpoint {
+    /// Every input line before this one has been committed
+    next_line: u64,
+    /// Lines after `next_line` that have been committed already
+    committed_ahead: BTreeSet<u64>,
+    /// Number of entries in the database log
+    db_entries: usize,
+    /// Size of the database data file, in bytes
+    db_size: u64,
+}
+
+impl Checkpoint {
+    fn contains(&self, line: u64) -> bool {
+        line < self.next_line || self.committed_ahead.contains(&line)
+    }
+
+    fn add(&mut self, line: u64) {
+        if line == self.next_line {
+            self.next_line += 1;
+            while self.committed_ahead.remove(&self.next_line) {
+                self.next_line += 1;
+            }
+        } else {
+            self.committed_ahead.insert(line);
+        }
+    }
+
+    fn read(path: &Path, index: &Index) -> Result<Self> {
+        let contents = fs::read(path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Can't read checkpoint at {}: {}", path.display(), err),
+            )
+        })?;
+        let from_file = serde_json::from_slice(&contents).map_err(invalid_data)?;
+
+        // The payload is committed atomically with the index, so it
+        // wins if we crashed before the file got updated
+        if let Some(payload) = index.load_metas()?.payload {
+            let committed = serde_json::from_str(&payload).map_err(invalid_data)?;
+            if committed != from_file {
+                log::warn!("Checkpoint file is stale. Using the index payload instead");
+                return Ok(committed);
+            }
+        }
+
+        Ok(from_file)
+    }
+}
+
+fn invalid_data(err: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// How to handle a recipe found to be a duplicate of one loaded before
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DuplicatePolicy {
+    /// Leave it out of both the database and the index
+    Skip,
+    /// Keep it in the database so it can still be fetched, but leave it
+    /// out of the index, folding it into the existing recipe's cluster
+    Merge,
+    /// Load it as usual, recording its cluster in the index
+    Tag,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(src: &str) -> std::result::Result<Self, Self::Err> {
+        match src {
+            "skip" => Ok(DuplicatePolicy::Skip),
+            "merge" => Ok(DuplicatePolicy::Merge),
+            "tag" => Ok(DuplicatePolicy::Tag),
+            other => Err(format!(
+                "Unknown duplicate policy '{}'. Expected skip, merge or tag",
+                other
+            )),
+        }
+    }
+}
+
+/// Recreates the clusters of an interrupted load from its canonical
+/// recipes, i.e. the indexed ones that are their own cluster
+fn rebuild_deduplicator(
+    index: &Index,
+    fields: &RecipeIndex,
+    db_path: &Path,
+    threshold: f32,
+) -> Result<Deduplicator> {
+    let db = DatabaseReader::<Recipe>::open(db_path)?;
+    let mut dedup = Deduplicator::new(threshold);
+
+    for reader in index.reader()?.searcher().segment_readers() {
+        let ids = reader
+            .fast_fields()
+            .u64(fields.id)
+            .expect("id is a fast field");
+        // Without clusters in the index, every recipe is its own
+        let clusters = fields.cluster_id.map(|field| {
+            reader
+                .fast_fields()
+                .u64(field)
+                .expect("cluster_id is a fast field")
+        });
+
+        for doc in 0..reader.max_doc() {
+            let id = ids.get(doc);
+            if reader.is_deleted(doc) || clusters.as_ref().map_or(false, |c| c.get(doc) != id) {
+                continue;
+            }
+
+            let recipe = db.find_by_id(id).ok_or_else(|| {
+                TantivyError::InvalidArgument(format!("Recipe {} missing from database", id))
+            })??;
+            dedup.insert(id, Signature::new(&recipe));
+        }
+    }
+
+    Ok(dedup)
+}
+
+/// A parsed recipe and the input line it came from. The document is
+/// only present when it hasn't been added to the index yet and the
+/// signature only when detecting duplicates
+struct Prepared {
+    line_number: u64,
+    recipe: Recipe,
+    document: Option<Document>,
+    signature: Option<Signature>,
+}
+
+struct DiskWriter {
+    db: DatabaseWriter<Recipe>,
+    fields: RecipeIndex,
+    dedup: Option<(DuplicatePolicy, Deduplicator)>,
+    num_duplicates: usize,
+    checkpoint: Checkpoint,
+    checkpoint_path: PathBuf,
+    num_recipes: usize,
+    preserve_order: bool,
+    /// Recipes waiting for the ones preceding them in the input
+    /// before they can be written (only used when preserving order)
+    pending: BTreeMap<u64, Prepared>,
+}
+
+impl DiskWriter {
+    fn receive(&mut self, writer: &IndexWriter, prepared: Prepared) -> Result<()> {
+        if !self.preserve_order {
+            return self.write(writer, prepared);
+        }
+
+        self.pending.insert(prepared.line_number, prepared);
+        while let Some(prepared) = self.pending.remove(&self.checkpoint.next_line) {
+            self.write(writer, prepared)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, writer: &IndexWriter, mut prepared: Prepared) -> Result<()> {
+        let mut skip = false;
+
+        if let Some((policy, dedup)) = &mut self.dedup {
+            let signature = prepared.signature.take().expect("signature is computed");
+            if let Some(cluster_id) = dedup.insert(prepared.recipe.recipe_id, signature) {
+                self.num_duplicates += 1;
+                match policy {
+                    DuplicatePolicy::Skip => skip = true,
+                    DuplicatePolicy::Merge => prepared.document = None,
+                    DuplicatePolicy::Tag => {
+                        prepared.document = Some(
+                            self.fields
+                                .make_clustered_document(&prepared.recipe, cluster_id),
+                        )
+                    }
+                }
+            }
+        }
+
+        if !skip {
+            if let Some(document) = prepared.document {
+                writer.add_document(document);
+            }
+
+            self.db.append(&prepared.recipe)?;
+            self.num_recipes += 1;
+        }
+
+        self.checkpoint.add(prepared.line_number);
+
+        Ok(())
+    }
+
+    fn commit(&mut self, writer: &mut IndexWriter) -> Result<()> {
+        self.db.flush()?;
+        self.checkpoint.db_entries = self.db.num_entries()?;
+        self.checkpoint.db_size = self.db.data_size()?;
+
+        let payload = serde_json::to_string(&self.checkpoint).expect("checkpoint is serializable");
+
+        let mut prepared = writer.prepare_commit()?;
+        prepared.set_payload(&payload);
+        prepared.commit()?;
+
+        // Only written after committing so it never gets ahead of the index
+        let tmp_path = self.checkpoint_path.with_extension("tmp");
+        fs::write(&tmp_path, payload)?;
+        fs::rename(&tmp_path, &self.checkpoint_path)?;
+
+        Ok(())
+    }
+}
+
+/// Counts the bytes read from the underlying reader
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+struct Progress {
+    started: Instant,
+    last_report: Instant,
+    report_every: Duration,
+    bytes_read: Arc<AtomicU64>,
+    input_size: Option<u64>,
+}
+
+impl Progress {
+    fn maybe_report(&mut self, num_recipes: usize) {
+        if self.last_report.elapsed() < self.report_every {
+            return;
+        }
+        self.last_report = Instant::now();
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = num_recipes as f64 / elapsed;
+
+        let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+        if let Some(size) = self.input_size.filter(|&size| size > 0 && bytes_read > 0) {
+            let done = bytes_read as f64 / size as f64;
+            log::info!(
+                "Progress: {} recipes ({:.0}/s). {:.1}% of the input read. ETA {:.0} secs",
+                num_recipes,
+                rate,
+                done * 100.0,
+                elapsed * (1.0 - done) / done
+            );
+        } else {
+            log::info!("Progress: {} recipes ({:.0}/s)", num_recipes, rate);
+        }
+    }
 }
 
 fn load(options: LoadOptions) -> Result<()> {
@@ -37,23 +329,67 @@ fn load(options: LoadOptions) -> Result<()> {
     let base_path = Path::new(options.output_dir.as_str());
     let db_path = base_path.join("database");
     let index_path = base_path.join("tantivy");
+    let checkpoint_path = base_path.join("checkpoint.json");
+
+    let (index, fields, db, checkpoint, dedup) = if options.resume {
+        let index = Index::open_in_dir(&index_path)?;
+        let fields = RecipeIndex::try_from(&index.schema())?;
+        let checkpoint = Checkpoint::read(&checkpoint_path, &index)?;
+        let db = DatabaseWriter::resume(&db_path, checkpoint.db_entries, checkpoint.db_size)?;
+        let dedup = match options.dedup {
+            Some(policy) => Some((
+                policy,
+                rebuild_deduplicator(&index, &fields, &db_path, options.dedup_threshold)?,
+            )),
+            None => None,
+        };
 
-    std::fs::create_dir_all(&db_path)?;
-    std::fs::create_dir(&index_path)?;
+        log::info!(
+            "Resuming from line {} with {} recipes in the database",
+            checkpoint.next_line,
+            checkpoint.db_entries
+        );
 
-    let mut builder = SchemaBuilder::new();
+        (index, fields, db, checkpoint, dedup)
+    } else {
+        std::fs::create_dir_all(&db_path)?;
+        std::fs::create_dir(&index_path)?;
 
-    let fields = RecipeIndex::from(&mut builder);
+        let mut builder = SchemaBuilder::new();
 
-    let index = Index::open_or_create(MmapDirectory::open(&index_path)?, builder.build())?;
+        let fields = RecipeIndex::from(&mut builder);
+
+        let index = Index::open_or_create(MmapDirectory::open(&index_path)?, builder.build())?;
+
+        (
+            index,
+            fields,
+            DatabaseWriter::new(&db_path)?,
+            Checkpoint::default(),
+            options
+                .dedup
+                .map(|policy| (policy, Deduplicator::new(options.dedup_threshold))),
+        )
+    };
 
     // A SpMc channel to paralellize decode and index preparation
-    let (line_sender, line_receiver) = unbounded::<String>();
+    let (line_sender, line_receiver) = unbounded::<(u64, String)>();
     // A MpSc channel to control index commit and write to db
     let (recipe_sender, recipe_receiver) = channel();
 
-    let buffer_size = options.buffer_size;
-    let writer = Arc::new(RwLock::new(index.writer(buffer_size * 1_000_000)?));
+    let buffer_size = options.buffer_size * 1_000_000;
+    let preserve_order = options.preserve_order;
+    let detect_duplicates = dedup.is_some();
+    // Whether a recipe gets indexed can only be decided by the disk
+    // writer, which is also the one that knows the order to keep
+    let index_on_disk_writer = preserve_order || detect_duplicates;
+    // Multiple indexing threads would spread documents over segments
+    // in whatever order they happen to be picked up
+    let writer = Arc::new(RwLock::new(if preserve_order {
+        index.writer_with_num_threads(1, buffer_size)?
+    } else {
+        index.writer(buffer_size)?
+    }));
 
     let num_producers = options.num_producers;
     let mut workers = Vec::with_capacity(num_producers);
@@ -64,57 +400,158 @@ fn load(options: LoadOptions) -> Result<()> {
 
         let fields = fields.clone();
         workers.push(spawn(move || {
-            for line in receiver {
+            for (line_number, line) in receiver {
                 let recipe: Recipe =
                     serde_json::from_str(line.as_ref()).expect("valid recipe json");
 
-                writer
-                    .read()
-                    .unwrap()
-                    .add_document(fields.make_document(&recipe));
-
-                recipe_sender.send(recipe).expect("send always works");
+                let document = fields.make_document(&recipe);
+
+                let signature = if detect_duplicates {
+                    Some(Signature::new(&recipe))
+                } else {
+                    None
+                };
+
+                let mut prepared = Prepared {
+                    line_number,
+                    recipe,
+                    document: None,
+                    signature,
+                };
+
+                if index_on_disk_writer {
+                    // Indexing is left to the disk writer
+                    prepared.document = Some(document);
+                    recipe_sender.send(prepared).expect("send always works");
+                } else {
+                    // The read lock is only released after sending the
+                    // recipe, so when the disk writer grabs the write lock
+                    // every indexed recipe is guaranteed to be in the channel
+                    let writer = writer.read().unwrap();
+                    writer.add_document(document);
+
+                    recipe_sender.send(prepared).expect("send always works");
+                }
             }
         }))
     }
 
-    let disk_writer = spawn(move || -> Result<()> {
-        let mut db = DatabaseWriter::new(db_path)?;
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let input_size = if options.inputs.is_empty() {
+        None
+    } else {
+        let mut size = 0;
+        for path in &options.inputs {
+            size += fs::metadata(path)?.len();
+        }
+        Some(size)
+    };
 
+    let mut progress = Progress {
+        started: Instant::now(),
+        last_report: Instant::now(),
+        report_every: Duration::from_secs(options.report_every),
+        bytes_read: bytes_read.clone(),
+        input_size,
+    };
+
+    let commit_every = options.commit_every;
+    let skip = checkpoint.clone();
+    let mut disk = DiskWriter {
+        db,
+        fields,
+        dedup,
+        num_duplicates: 0,
+        checkpoint,
+        checkpoint_path,
+        num_recipes: 0,
+        preserve_order,
+        pending: BTreeMap::new(),
+    };
+    let disk_writer = spawn(move || -> Result<()> {
         let cur = Instant::now();
-        let mut num_recipes = 0;
+        let mut last_commit = 0;
+
+        for prepared in recipe_receiver.iter() {
+            disk.receive(&*writer.read()?, prepared)?;
 
-        for recipe in recipe_receiver {
-            num_recipes += 1;
-            db.append(&recipe)?;
+            if disk.num_recipes - last_commit >= commit_every {
+                let mut writer = writer.write()?;
 
-            if num_recipes % options.commit_every == 0 {
-                writer.write()?.commit()?;
+                // Workers only index recipes themselves when the
+                // disk writer doesn't, so that's the only case
+                // where the channel may hold indexed recipes
+                if !index_on_disk_writer {
+                    for prepared in recipe_receiver.try_iter() {
+                        disk.receive(&writer, prepared)?;
+                    }
+                }
+
+                disk.commit(&mut writer)?;
+                last_commit = disk.num_recipes;
 
                 log::info!(
                     "DiskWriter: {} Documents so far (@ {} secs).",
-                    num_recipes,
+                    disk.num_recipes,
                     cur.elapsed().as_secs()
                 );
             }
+
+            progress.maybe_report(disk.num_recipes);
         }
 
-        writer.write()?.commit()?;
+        debug_assert!(disk.pending.is_empty());
+        disk.commit(&mut *writer.write()?)?;
 
         log::info!(
             "DiskWriter: Wrote {} documents in {} seconds",
-            num_recipes,
+            disk.num_recipes,
             cur.elapsed().as_secs()
         );
 
+        if let Some((policy, dedup)) = &disk.dedup {
+            log::info!(
+                "DiskWriter: Found {} duplicates ({:?}) in {} clusters",
+                disk.num_duplicates,
+                policy,
+                dedup.num_clusters()
+            );
+        }
+
         Ok(())
     });
 
-    for line in io::stdin().lock().lines() {
-        line_sender.send(line?).unwrap();
-    }
+    // Decompression is expensive enough to deserve its own thread
+    let inputs = options.inputs;
+    let line_reader = spawn(move || -> io::Result<()> {
+        let mut line_number = 0;
+        let mut send_lines = |reader: Box<dyn BufRead + Send>| -> io::Result<()> {
+            for line in reader.lines() {
+                let line = line?;
+                if !skip.contains(line_number) {
+                    line_sender.send((line_number, line)).unwrap();
+                }
+                line_number += 1;
+            }
+            Ok(())
+        };
+
+        if inputs.is_empty() {
+            send_lines(decompressed(io::stdin())?)?;
+        }
 
-    drop(line_sender);
+        for path in inputs {
+            log::info!("Reading recipes from {}", path.display());
+            send_lines(decompressed(CountingReader {
+                inner: File::open(&path)?,
+                count: bytes_read.clone(),
+            })?)?;
+        }
+
+        Ok(())
+    });
+
+    line_reader.join().unwrap()?;
 
     for worker in workers.into_iter() {
         worker.join().unwrap();
@@ -129,9 +566,37 @@ fn load(options: LoadOptions) -> Result<()> {
     Ok(())
 }
 
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Wraps the input with a decoder matching its compression format,
+/// detected by peeking at its first bytes. Plain text passes through
+fn decompressed<R: 'static + Read + Send>(input: R) -> io::Result<Box<dyn BufRead + Send>> {
+    let mut reader = BufReader::new(input);
+    let magic = reader.fill_buf()?;
+
+    let decoder: Box<dyn Read + Send> = if magic.starts_with(BZIP2_MAGIC) {
+        Box::new(MultiBzDecoder::new(reader))
+    } else if magic.starts_with(GZIP_MAGIC) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        return Ok(Box::new(reader));
+    };
+
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
 const BUFFER_SIZE: &str = "BUFFER_SIZE";
 const COMMIT_EVERY: &str = "COMMIT_EVERY";
 const NUM_PRODUCERS: &str = "NUM_PRODUCERS";
+const REPORT_EVERY: &str = "REPORT_EVERY";
+const RESUME: &str = "RESUME";
+const PRESERVE_ORDER: &str = "PRESERVE_ORDER";
+const DEDUP: &str = "DEDUP";
+const DEDUP_THRESHOLD: &str = "DEDUP_THRESHOLD";
 
 fn get_usize_from_env_or(key: &str, default: usize) -> usize {
     env::var(key)
@@ -147,17 +612,41 @@ fn main() -> Result<()> {
         .nth(1)
         .expect("First parameter must be the output directory");
 
+    // Every other parameter is an input file: plain, bzip2, gzip or zstd
+    let inputs = env::args().skip(2).map(PathBuf::from).collect();
+
     let buffer_size = get_usize_from_env_or(BUFFER_SIZE, 1000);
 
     let commit_every = get_usize_from_env_or(COMMIT_EVERY, 300_000);
 
     let num_producers = get_usize_from_env_or(NUM_PRODUCERS, 4);
 
+    let report_every = get_usize_from_env_or(REPORT_EVERY, 30) as u64;
+
+    let resume = flag_from_env(RESUME);
+
+    let preserve_order = flag_from_env(PRESERVE_ORDER);
+
+    let dedup = env::var(DEDUP)
+        .ok()
+        .map(|v| DuplicatePolicy::from_str(&v).expect("valid duplicate policy"));
+
+    let dedup_threshold = env::var(DEDUP_THRESHOLD)
+        .ok()
+        .map(|v| f32::from_str(&v).expect("valid f32"))
+        .unwrap_or(0.8);
+
     let options = LoadOptions {
         output_dir,
+        inputs,
         buffer_size,
         commit_every,
         num_producers,
+        report_every,
+        resume,
+        preserve_order,
+        dedup,
+        dedup_threshold,
     };
 
     load(options)
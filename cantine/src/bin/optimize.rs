@@ -0,0 +1,274 @@
+use std::{
+    cmp::Ordering, collections::HashMap, convert::TryFrom, env, fs, path::Path, str::FromStr,
+};
+
+use env_logger;
+use futures::{executor::block_on, future::join_all};
+use log::info;
+use serde_json;
+
+use tantivy::{
+    self,
+    merge_policy::NoMergePolicy,
+    schema::{Field, FieldType},
+    Index, IndexWriter, Result, SegmentId, SegmentReader, TantivyError,
+};
+
+use cantine::{
+    database::DatabaseReader,
+    index::RecipeIndex,
+    model::{Recipe, RecipeId, Sort},
+};
+
+/// Compacts an index created by `load` for faster searching
+#[derive(Debug)]
+pub struct OptimizeOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// Maximum number of segments to leave the index with
+    num_segments: usize,
+    /// Rewrite the index so that documents are ordered by this field
+    sort_by: Option<Sort>,
+    /// Path to the directory `load` wrote to
+    base_dir: String,
+}
+
+fn optimize(options: OptimizeOptions) -> Result<()> {
+    let base_path = Path::new(&options.base_dir);
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    let recipe_index = RecipeIndex::try_from(&index.schema())?;
+
+    let num_segments = index.searchable_segment_ids()?.len();
+    info!(
+        "Optimizing index with {} segments. Options: {:?}",
+        num_segments, options
+    );
+
+    if let Some(sort) = &options.sort_by {
+        let (field, ascending) = sort_field(&recipe_index, sort).ok_or_else(|| {
+            TantivyError::InvalidArgument(format!("Can't sort the index by {:?}", sort))
+        })?;
+
+        let sorted_path = base_path.join("tantivy.sorted");
+        let old_path = base_path.join("tantivy.old");
+
+        let order = sorted_ids(&index, &recipe_index, field, ascending)?;
+        info!("Rewriting {} documents in order", order.len());
+
+        let database = DatabaseReader::<Recipe>::open(base_path.join("database"))?;
+
+        fs::create_dir(&sorted_path)?;
+        let sorted = Index::create_in_dir(&sorted_path, index.schema())?;
+        let mut writer = sorted.writer_with_num_threads(1, options.buffer_size * 1_000_000)?;
+        writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        for &(id, cluster_id) in order.iter() {
+            let recipe = database.find_by_id(id).ok_or_else(|| {
+                TantivyError::InvalidArgument(format!("Recipe {} missing from database", id))
+            })??;
+            writer.add_document(recipe_index.make_clustered_document(&recipe, cluster_id));
+        }
+        writer.commit()?;
+
+        // Merging keeps the order of the given segments, so they are
+        // grouped following the order of their first document
+        let positions: HashMap<RecipeId, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(pos, &(id, _))| (id, pos))
+            .collect();
+        let mut segments = Vec::new();
+        for reader in sorted.reader()?.searcher().segment_readers() {
+            let first_id = reader
+                .fast_fields()
+                .u64(recipe_index.id)
+                .expect("id is a u64 fast field")
+                .get(0);
+            segments.push((positions[&first_id], reader.segment_id()));
+        }
+        segments.sort();
+        let segment_ids = segments.into_iter().map(|(_, id)| id).collect();
+
+        merge(writer, segment_ids, options.num_segments)?;
+
+        fs::rename(&index_path, &old_path)?;
+        fs::rename(&sorted_path, &index_path)?;
+        fs::remove_dir_all(&old_path)?;
+    } else {
+        let writer = index.writer(options.buffer_size * 1_000_000)?;
+        writer.set_merge_policy(Box::new(NoMergePolicy));
+
+        let segment_ids = index.searchable_segment_ids()?;
+        merge(writer, segment_ids, options.num_segments)?;
+    }
+
+    let index = Index::open_in_dir(&index_path)?;
+    info!(
+        "Done! Index now has {} segments",
+        index.searchable_segment_ids()?.len()
+    );
+
+    Ok(())
+}
+
+/// Merges contiguous runs of `segment_ids` so that at most
+/// `num_segments` remain, then cleans up the merged files
+fn merge(mut writer: IndexWriter, segment_ids: Vec<SegmentId>, num_segments: usize) -> Result<()> {
+    let group_size = (segment_ids.len() + num_segments - 1) / num_segments;
+
+    if group_size > 1 {
+        let merges = segment_ids
+            .chunks(group_size)
+            .filter(|group| group.len() > 1)
+            .map(|group| writer.merge(group))
+            .collect::<Vec<_>>();
+
+        info!(
+            "Merging {} segments into {}",
+            segment_ids.len(),
+            merges.len()
+        );
+        for result in block_on(join_all(merges)) {
+            result?;
+        }
+    }
+
+    block_on(writer.garbage_collect_files())?;
+    writer.wait_merging_threads()
+}
+
+/// Every live recipe id in the index, along with its cluster id,
+/// ordered by the given fast field
+fn sorted_ids(
+    index: &Index,
+    recipe_index: &RecipeIndex,
+    field: Field,
+    ascending: bool,
+) -> Result<Vec<(RecipeId, RecipeId)>> {
+    let is_u64 = match index.schema().get_field_entry(field).field_type() {
+        FieldType::U64(_) => true,
+        FieldType::F64(_) => false,
+        _ => {
+            return Err(TantivyError::SchemaError(
+                "Can only sort by u64 or f64 fields".to_string(),
+            ))
+        }
+    };
+
+    let mut keyed = Vec::new();
+    for reader in index.reader()?.searcher().segment_readers() {
+        let ids = reader
+            .fast_fields()
+            .u64(recipe_index.id)
+            .expect("id is a u64 fast field");
+        let clusters = recipe_index.cluster_id.map(|field| {
+            reader
+                .fast_fields()
+                .u64(field)
+                .expect("cluster_id is a u64 fast field")
+        });
+        let keys = fast_field_as_f64(reader, field, is_u64);
+
+        for doc in 0..reader.max_doc() {
+            if !reader.is_deleted(doc) {
+                let id = ids.get(doc);
+                let cluster_id = clusters.as_ref().map_or(id, |c| c.get(doc));
+                keyed.push((keys(doc), id, cluster_id));
+            }
+        }
+    }
+
+    keyed.sort_by(|(ka, ida, _), (kb, idb, _)| {
+        let by_key = ka.partial_cmp(kb).unwrap_or(Ordering::Equal);
+        if ascending {
+            by_key.then(ida.cmp(idb))
+        } else {
+            by_key.reverse().then(idb.cmp(ida))
+        }
+    });
+
+    Ok(keyed
+        .into_iter()
+        .map(|(_, id, cluster_id)| (id, cluster_id))
+        .collect())
+}
+
+fn fast_field_as_f64(
+    reader: &SegmentReader,
+    field: Field,
+    is_u64: bool,
+) -> Box<dyn Fn(u32) -> f64> {
+    let fast_fields = reader.fast_fields();
+    if is_u64 {
+        let values = fast_fields.u64(field).expect("field is a u64 fast field");
+        Box::new(move |doc| values.get(doc) as f64)
+    } else {
+        let values = fast_fields.f64(field).expect("field is a f64 fast field");
+        Box::new(move |doc| values.get(doc))
+    }
+}
+
+/// The fast field backing a sort order and whether it's ascending
+fn sort_field(recipe_index: &RecipeIndex, sort: &Sort) -> Option<(Field, bool)> {
+    let features = &recipe_index.features;
+    match sort {
+        Sort::Relevance | Sort::RelevanceAsc => None,
+        Sort::Calories => Some((features.calories, false)),
+        Sort::CaloriesAsc => Some((features.calories, true)),
+        Sort::CarbContent => Some((features.carb_content, false)),
+        Sort::CarbContentAsc => Some((features.carb_content, true)),
+        Sort::CookTime => Some((features.cook_time, false)),
+        Sort::CookTimeAsc => Some((features.cook_time, true)),
+        Sort::FatContent => Some((features.fat_content, false)),
+        Sort::FatContentAsc => Some((features.fat_content, true)),
+        Sort::InstructionsLength => Some((features.instructions_length, false)),
+        Sort::InstructionsLengthAsc => Some((features.instructions_length, true)),
+        Sort::NumIngredients => Some((features.num_ingredients, false)),
+        Sort::NumIngredientsAsc => Some((features.num_ingredients, true)),
+        Sort::PrepTime => Some((features.prep_time, false)),
+        Sort::PrepTimeAsc => Some((features.prep_time, true)),
+        Sort::ProteinContent => Some((features.protein_content, false)),
+        Sort::ProteinContentAsc => Some((features.protein_content, true)),
+        Sort::TotalTime => Some((features.total_time, false)),
+        Sort::TotalTimeAsc => Some((features.total_time, true)),
+    }
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const NUM_SEGMENTS: &str = "NUM_SEGMENTS";
+const SORT_BY: &str = "SORT_BY";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter must be the directory load wrote to");
+
+    let buffer_size = get_usize_from_env_or(BUFFER_SIZE, 1000);
+
+    let num_segments = get_usize_from_env_or(NUM_SEGMENTS, 1).max(1);
+
+    // Same names the search api uses: "total_time", "calories_asc", ...
+    let sort_by = env::var(SORT_BY).ok().map(|name| {
+        serde_json::from_value(serde_json::Value::String(name)).expect("valid sort order")
+    });
+
+    let options = OptimizeOptions {
+        base_dir,
+        buffer_size,
+        num_segments,
+        sort_by,
+    };
+
+    optimize(options)
+}
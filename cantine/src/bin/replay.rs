@@ -0,0 +1,158 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+    time::Instant,
+};
+
+use env_logger;
+use serde_json;
+use tantivy::{Index, Result};
+
+use cantine::{
+    database::DatabaseReader,
+    model::{Recipe, RecipeId},
+    querylog::LoggedQuery,
+    search::{cursor_to_after, SearchConfig, SearchState},
+};
+
+struct Changed {
+    location: String,
+    fulltext: Option<String>,
+    logged_total: usize,
+    replayed_total: usize,
+    logged_ids: Vec<RecipeId>,
+    replayed_ids: Vec<RecipeId>,
+}
+
+/// Nearest-rank percentile of an already sorted slice
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+fn print_latencies(source: &str, latencies: &mut [u64]) {
+    latencies.sort_unstable();
+    let millis = |micros: u64| micros as f64 / 1000.0;
+
+    println!(
+        "{},{:.3},{:.3},{:.3},{:.3}",
+        source,
+        millis(percentile(latencies, 50)),
+        millis(percentile(latencies, 90)),
+        millis(percentile(latencies, 99)),
+        millis(latencies.last().copied().unwrap_or(0)),
+    );
+}
+
+fn join_ids(ids: &[RecipeId]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+const SEARCH_CONFIG: &str = "SEARCH_CONFIG";
+const AGG_THRESHOLD: &str = "AGG_THRESHOLD";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter is a path to a directory");
+
+    // Every other parameter is a query log, replayed in the given order
+    let logs = env::args().skip(2).collect::<Vec<_>>();
+    assert!(!logs.is_empty(), "Missing query log(s) to replay");
+
+    let config = match env::var(SEARCH_CONFIG) {
+        Ok(path) => SearchConfig::load(&path)?,
+        Err(_) => SearchConfig::default(),
+    };
+
+    let agg_threshold = env::var(AGG_THRESHOLD)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(usize::MAX);
+
+    let base_path = Path::new(&base_dir);
+    let index = Index::open_in_dir(base_path.join("tantivy"))?;
+    let database = DatabaseReader::<Recipe>::open(base_path.join("database"))?;
+    let state = SearchState::new(&index, &config, agg_threshold)?;
+
+    let mut num_queries = 0;
+    let mut num_skipped = 0;
+    let mut logged_latencies = Vec::new();
+    let mut replayed_latencies = Vec::new();
+    let mut changed = Vec::new();
+
+    for log_path in logs.iter() {
+        for (line_number, line) in BufReader::new(File::open(log_path)?).lines().enumerate() {
+            let location = format!("{}:{}", log_path, line_number + 1);
+            let logged: LoggedQuery = serde_json::from_str(&line?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            let after = match &logged.query.after {
+                Some(cursor) => {
+                    if let Some(after) = cursor_to_after(&database, cursor) {
+                        Some(after)
+                    } else {
+                        log::warn!("Skipping {}: cursor points at unknown recipe", location);
+                        num_skipped += 1;
+                        continue;
+                    }
+                }
+                None => None,
+            };
+
+            let fulltext = logged.query.fulltext.clone();
+
+            let start = Instant::now();
+            let (total_found, recipe_ids, _after, _agg) = state.search(logged.query, after)?;
+            replayed_latencies.push(start.elapsed().as_micros() as u64);
+            logged_latencies.push(logged.elapsed_micros);
+            num_queries += 1;
+
+            if total_found != logged.total_found || recipe_ids != logged.recipe_ids {
+                changed.push(Changed {
+                    location,
+                    fulltext,
+                    logged_total: logged.total_found,
+                    replayed_total: total_found,
+                    logged_ids: logged.recipe_ids,
+                    replayed_ids: recipe_ids,
+                });
+            }
+        }
+    }
+
+    println!("queries,skipped,changed");
+    println!("{},{},{}", num_queries, num_skipped, changed.len());
+    println!();
+
+    println!("source,p50_ms,p90_ms,p99_ms,max_ms");
+    print_latencies("logged", &mut logged_latencies);
+    print_latencies("replayed", &mut replayed_latencies);
+    println!();
+
+    println!("location,fulltext,logged_total,replayed_total,logged_ids,replayed_ids");
+    for diff in changed {
+        println!(
+            "{},\"{}\",{},{},{},{}",
+            diff.location,
+            diff.fulltext.unwrap_or_default().replace('"', "\"\""),
+            diff.logged_total,
+            diff.replayed_total,
+            join_ids(&diff.logged_ids),
+            join_ids(&diff.replayed_ids),
+        );
+    }
+
+    Ok(())
+}
@@ -95,6 +95,49 @@ where
         })
     }
 
+    /// Opens an existing database for writing, discarding everything
+    /// past its first `num_entries` entries and `data_size` bytes of
+    /// data. Values from `num_entries` and `data_size` after a `flush` are
+    /// valid inputs.
+    pub fn resume<P: AsRef<Path>>(base_dir: P, num_entries: usize, data_size: u64) -> Result<Self> {
+        let mut log = StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?;
+        log.truncate(num_entries)?;
+
+        let mut datafile = OpenOptions::new()
+            .write(true)
+            .open(base_dir.as_ref().join(DATA_FILE))?;
+
+        if datafile.metadata()?.len() < data_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Data file is smaller than expected",
+            ));
+        }
+
+        datafile.set_len(data_size)?;
+        datafile.seek(SeekFrom::End(0))?;
+
+        Ok(Self {
+            writer: BufWriter::new(datafile),
+            log,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Number of entries in the database
+    pub fn num_entries(&self) -> Result<usize> {
+        self.log.len()
+    }
+
+    /// Size of the data written so far, in bytes
+    pub fn data_size(&mut self) -> Result<u64> {
+        self.writer.seek(SeekFrom::Current(0))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
     pub fn append(&mut self, item: &T) -> Result<()> {
         let encoded = bincode::serialize(item)
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failure encoding input"))?;
@@ -186,4 +229,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn resume() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+
+        let kept = Named(0, Uuid::new_v4(), "kept");
+        db_writer.append(&kept)?;
+        db_writer.flush()?;
+
+        let num_entries = db_writer.num_entries()?;
+        let data_size = db_writer.data_size()?;
+
+        db_writer.append(&Named(1, Uuid::new_v4(), "discarded"))?;
+        drop(db_writer);
+
+        let mut db_writer = DatabaseWriter::resume(basedir.path(), num_entries, data_size)?;
+        assert_eq!(num_entries, db_writer.num_entries()?);
+
+        let appended = Named(2, Uuid::new_v4(), "appended");
+        db_writer.append(&appended)?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::open(basedir.path())?;
+
+        assert_eq!(2, db_reader.ids().count());
+        assert_eq!(Some(kept), db_reader.find_by_id(0).transpose()?);
+        assert_eq!(None, db_reader.find_by_id(1).transpose()?);
+        assert_eq!(Some(appended), db_reader.find_by_id(2).transpose()?);
+
+        Ok(())
+    }
 }
@@ -84,6 +84,17 @@ where
     pub fn append(&mut self, item: &T) -> Result<()> {
         self.file.write_all(item.as_bytes())
     }
+
+    pub fn truncate(&mut self, num_items: usize) -> Result<()> {
+        if num_items > self.len()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't truncate log to {} items: too short", num_items),
+            ));
+        }
+
+        self.file.set_len((num_items * size_of::<T>()) as u64)
+    }
 }
 
 #[cfg(test)]
@@ -121,4 +132,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn truncate() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let log_path = tmpdir.path().join("testlog");
+
+        {
+            let mut log = StructuredLog::new(&log_path)?;
+
+            for i in 0..10 {
+                log.append(&U64::<NativeEndian>::new(i))?;
+            }
+
+            assert!(log.truncate(11).is_err());
+
+            log.truncate(4)?;
+            assert_eq!(4, log.len()?);
+
+            // Appending continues from the truncation point
+            log.append(&U64::<NativeEndian>::new(4))?;
+        }
+
+        let log = StructuredLog::new(&log_path)?;
+
+        let mut wanted: u64 = 0;
+        log.for_each_entry(|e: &U64<NativeEndian>| {
+            assert_eq!(wanted, e.get());
+            wanted += 1;
+        })?;
+        assert_eq!(5, wanted);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,261 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::model::{Recipe, RecipeId};
+
+/// Number of min-hashes kept per recipe
+const NUM_HASHES: usize = 32;
+/// Locality-sensitive hashing: near-duplicate candidates are the
+/// recipes sharing every min-hash of at least one band
+const NUM_BANDS: usize = 8;
+const ROWS_PER_BAND: usize = NUM_HASHES / NUM_BANDS;
+
+/// Number of consecutive words in each shingle
+const SHINGLE_SIZE: usize = 3;
+
+/// What's needed to tell whether two recipes are the same
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// Hash of the normalized name and (sorted) ingredients
+    exact: u64,
+    /// MinHash over the shingles of the ingredients and instructions.
+    /// Empty when there's not enough text to shingle
+    minhash: Vec<u32>,
+}
+
+impl Signature {
+    pub fn new(recipe: &Recipe) -> Self {
+        let mut ingredients: Vec<_> = recipe.ingredients.iter().map(|i| normalize(i)).collect();
+        ingredients.sort();
+
+        let mut hasher = DefaultHasher::new();
+        normalize(&recipe.name).hash(&mut hasher);
+        ingredients.hash(&mut hasher);
+        let exact = hasher.finish();
+
+        let words = recipe
+            .ingredients
+            .iter()
+            .chain(recipe.instructions.iter())
+            .map(|text| normalize(text))
+            .collect::<Vec<_>>();
+        let words = words.iter().flat_map(|t| t.split(' ')).collect::<Vec<_>>();
+
+        let mut minhash = Vec::new();
+        if words.len() >= SHINGLE_SIZE {
+            minhash = vec![u32::MAX; NUM_HASHES];
+            for shingle in words.windows(SHINGLE_SIZE) {
+                let mut hasher = DefaultHasher::new();
+                shingle.hash(&mut hasher);
+                let hash = hasher.finish();
+
+                for (seed, min) in minhash.iter_mut().enumerate() {
+                    *min = (*min).min(permute(hash, seed));
+                }
+            }
+        }
+
+        Self { exact, minhash }
+    }
+
+    /// Estimated Jaccard similarity between the shingle sets
+    fn similarity(&self, other: &Self) -> f32 {
+        if self.minhash.is_empty() || other.minhash.is_empty() {
+            return 0.0;
+        }
+
+        let same = self
+            .minhash
+            .iter()
+            .zip(other.minhash.iter())
+            .filter(|(a, b)| a == b)
+            .count();
+
+        same as f32 / NUM_HASHES as f32
+    }
+
+    fn bands(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.minhash
+            .chunks(ROWS_PER_BAND)
+            .enumerate()
+            .map(|(band, rows)| {
+                let mut hasher = DefaultHasher::new();
+                rows.hash(&mut hasher);
+                (band, hasher.finish())
+            })
+    }
+}
+
+/// Groups recipes into clusters of duplicates. The first recipe seen
+/// in a cluster is its canonical one and its id is the cluster id
+pub struct Deduplicator {
+    threshold: f32,
+    exact: HashMap<u64, RecipeId>,
+    bands: HashMap<(usize, u64), Vec<RecipeId>>,
+    signatures: HashMap<RecipeId, Signature>,
+}
+
+impl Deduplicator {
+    /// Recipes with estimated similarity of at least `threshold`
+    /// (within `[0, 1]`) are considered near-duplicates
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            exact: HashMap::new(),
+            bands: HashMap::new(),
+            signatures: HashMap::new(),
+        }
+    }
+
+    /// Number of clusters seen so far
+    pub fn num_clusters(&self) -> usize {
+        self.signatures.len()
+    }
+
+    /// Finds the cluster a recipe belongs to, returning its id if the
+    /// recipe is a duplicate. Otherwise the recipe starts a new cluster
+    pub fn insert(&mut self, id: RecipeId, signature: Signature) -> Option<RecipeId> {
+        if let Some(&canonical) = self.exact.get(&signature.exact) {
+            return Some(canonical);
+        }
+
+        let mut best: Option<(f32, RecipeId)> = None;
+        for key in signature.bands() {
+            for candidate in self.bands.get(&key).into_iter().flatten() {
+                let similarity = signature.similarity(&self.signatures[candidate]);
+                if similarity < self.threshold {
+                    continue;
+                }
+
+                // Ties go to the oldest cluster
+                let better = match best {
+                    Some((score, other)) => {
+                        similarity > score || (similarity == score && *candidate < other)
+                    }
+                    None => true,
+                };
+
+                if better {
+                    best = Some((similarity, *candidate));
+                }
+            }
+        }
+
+        if let Some((_, canonical)) = best {
+            return Some(canonical);
+        }
+
+        self.exact.insert(signature.exact, id);
+        for key in signature.bands() {
+            self.bands.entry(key).or_default().push(id);
+        }
+        self.signatures.insert(id, signature);
+
+        None
+    }
+}
+
+/// Lowercased alphanumeric words separated by a single space
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One of the `NUM_HASHES` hash functions, derived from a single hash
+/// via splitmix64
+fn permute(hash: u64, seed: usize) -> u32 {
+    let mut z = hash.wrapping_add((seed as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+
+    fn recipe(id: RecipeId, name: &str, ingredients: &[&str], instructions: &[&str]) -> Recipe {
+        Recipe {
+            uuid: Uuid::new_v4(),
+            recipe_id: id,
+            name: name.to_string(),
+            crawl_url: format!("https://example.com/{}", id),
+            ingredients: ingredients.iter().map(|i| i.to_string()).collect(),
+            instructions: instructions.iter().map(|i| i.to_string()).collect(),
+            images: Vec::new(),
+            similar_recipe_ids: Vec::new(),
+            features: Default::default(),
+        }
+    }
+
+    const INSTRUCTIONS: &[&str] = &[
+        "Preheat the oven to 180C and grease a baking tin with butter",
+        "Whisk the eggs with the sugar until pale and fluffy",
+        "Fold in the flour and the melted chocolate, then pour into the tin",
+        "Bake for about 25 minutes, until a skewer comes out clean",
+        "Let it cool down completely before slicing and serving",
+    ];
+
+    #[test]
+    fn normalization() {
+        assert_eq!("2 cups of flour", normalize("  2 Cups of FLOUR!"));
+        assert_eq!("", normalize(" ,.- "));
+    }
+
+    #[test]
+    fn exact_duplicates() {
+        let mut dedup = Deduplicator::new(0.8);
+
+        let original = recipe(1, "Brownies", &["2 eggs", "1 cup flour"], INSTRUCTIONS);
+        assert_eq!(None, dedup.insert(1, Signature::new(&original)));
+
+        // Ingredient order, casing and punctuation don't matter
+        let copy = recipe(2, "brownies!", &["1 Cup flour", "2 eggs"], &["Bake it."]);
+        assert_eq!(Some(1), dedup.insert(2, Signature::new(&copy)));
+
+        let other = recipe(3, "Brownies", &["3 eggs", "1 cup flour"], &["Bake it."]);
+        assert_eq!(None, dedup.insert(3, Signature::new(&other)));
+
+        assert_eq!(2, dedup.num_clusters());
+    }
+
+    #[test]
+    fn near_duplicates() {
+        let mut dedup = Deduplicator::new(0.8);
+        let ingredients = &["4 eggs", "200g sugar", "100g flour", "200g dark chocolate"];
+
+        let original = recipe(1, "Brownies", ingredients, INSTRUCTIONS);
+        assert_eq!(None, dedup.insert(1, Signature::new(&original)));
+
+        let mut tweaked = INSTRUCTIONS.to_vec();
+        tweaked[4] = "Let it cool down completely before serving";
+        let similar = recipe(2, "Fudgy Brownies", ingredients, &tweaked);
+        assert_eq!(Some(1), dedup.insert(2, Signature::new(&similar)));
+
+        let unrelated = recipe(
+            3,
+            "Salad",
+            &["1 head of lettuce", "2 tomatoes"],
+            &["Chop everything and toss with olive oil and salt"],
+        );
+        assert_eq!(None, dedup.insert(3, Signature::new(&unrelated)));
+    }
+
+    #[test]
+    fn short_recipes_only_match_exactly() {
+        let mut dedup = Deduplicator::new(0.0);
+
+        let a = recipe(1, "Toast", &["bread"], &[]);
+        let b = recipe(2, "Tea", &["tea"], &[]);
+
+        assert_eq!(None, dedup.insert(1, Signature::new(&a)));
+        assert_eq!(None, dedup.insert(2, Signature::new(&b)));
+    }
+}
@@ -0,0 +1,43 @@
+//! On/off knobs read from environment variables
+
+use std::env;
+
+/// Tells whether `value` turns a knob on: `1`, `true`, `yes` and
+/// `on` do, `0`, `false`, `no`, `off` and the empty string don't.
+/// Anything else is likely a typo and yields None
+pub fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "" | "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether the environment variable `key` is set to turn its knob on
+///
+/// Panics when it's set to a value `parse_flag` doesn't understand
+pub fn flag_from_env(key: &str) -> bool {
+    env::var(key).map_or(false, |value| {
+        parse_flag(&value).unwrap_or_else(|| panic!("{} must be a valid flag (1 or 0)", key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        for on in &["1", "true", "TRUE", "yes", "on", " 1\n"] {
+            assert_eq!(Some(true), parse_flag(on), "{:?}", on);
+        }
+
+        for off in &["", "0", "false", "False", "no", "off"] {
+            assert_eq!(Some(false), parse_flag(off), "{:?}", off);
+        }
+
+        for garbage in &["2", "ye", "enabled", "-1"] {
+            assert_eq!(None, parse_flag(garbage), "{:?}", garbage);
+        }
+    }
+}
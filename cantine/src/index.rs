@@ -8,23 +8,29 @@ use tantivy::{
     fastfield::FastFieldReader,
     query::Query,
     schema::{Field, Schema, SchemaBuilder, Value, FAST, INDEXED, STORED, TEXT},
-    DocId, Document, Result, Score, Searcher, SegmentLocalId, SegmentReader, TantivyError,
+    DocAddress, DocId, Document, Result, Score, Searcher, SegmentLocalId, SegmentReader,
+    TantivyError,
 };
 
 use crate::model::{
-    Features, FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesFilterFields, Recipe,
-    RecipeId, Sort,
+    Collapse, Features, FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesFilterFields,
+    Recipe, RecipeId, Sort,
 };
 
 use cantine_derive::{AggregableCollector, Filterable};
 
 use tique::conditional_collector::{
-    Ascending, CheckCondition, CollectionResult, ConditionForSegment, Descending, TopCollector,
+    Ascending, CheckCondition, CollapsingTopCollector, CollectionResult, ConditionForSegment,
+    Descending, TopCollector,
 };
 
 #[derive(Clone)]
 pub struct RecipeIndex {
     pub id: Field,
+    /// Missing from indices built before collapsing was supported
+    pub cluster_id: Option<Field>,
+    /// Ditto
+    pub crawl_host: Option<Field>,
 
     pub name: Field,
     pub ingredients: Field,
@@ -35,6 +41,8 @@ pub struct RecipeIndex {
 }
 
 const FIELD_ID: &str = "id";
+const FIELD_CLUSTER_ID: &str = "cluster_id";
+const FIELD_CRAWL_HOST: &str = "crawl_host";
 const FIELD_NAME: &str = "name";
 const FIELD_INGREDIENTS: &str = "ingredients";
 const FIELD_INSTRUCTIONS: &str = "instructions";
@@ -42,8 +50,20 @@ const FIELD_FEATURES_BINCODE: &str = "features_bincode";
 
 impl RecipeIndex {
     pub fn make_document(&self, recipe: &Recipe) -> Document {
+        self.make_clustered_document(recipe, recipe.recipe_id)
+    }
+
+    /// Like `make_document`, but for a recipe that belongs to the
+    /// duplicate cluster identified by `cluster_id`
+    pub fn make_clustered_document(&self, recipe: &Recipe, cluster_id: RecipeId) -> Document {
         let mut doc = Document::new();
         doc.add_u64(self.id, recipe.recipe_id);
+        if let Some(field) = self.cluster_id {
+            doc.add_u64(field, cluster_id);
+        }
+        if let Some(field) = self.crawl_host {
+            doc.add_u64(field, hash_host(&recipe.crawl_url));
+        }
 
         doc.add_text(self.name, recipe.name.as_str());
 
@@ -74,32 +94,107 @@ impl RecipeIndex {
         sort: Sort,
         after: Option<After>,
     ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
-        macro_rules! collect {
-            ($type: ty, $field:ident, $order:ident) => {
-                if let Some(after) = after {
+        self.collect(searcher, query, limit, sort, after, None)
+    }
+
+    /// Like `search` with `Sort::Relevance`, but skipping the recipes
+    /// that can't make it to the top when the query allows it. The
+    /// total is only exact up to `exact_total` recipes: past that it's
+    /// merely a lower bound
+    pub fn search_pruned(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        after: Option<After>,
+        exact_total: usize,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let result = if let Some(after) = after {
+            TopCollector::<Score, Descending, _>::new(limit, after.as_paginator(self.id))
+                .search_pruned(searcher, query, exact_total)?
+        } else {
+            TopCollector::<Score, Descending, _>::new(limit, true).search_pruned(
+                searcher,
+                query,
+                exact_total,
+            )?
+        };
+
+        self.render_result(searcher, result)
+    }
+
+    /// The field recipes are grouped by when collapsing, unless the
+    /// index predates it
+    pub fn collapse_field(&self, collapse: Collapse) -> Option<Field> {
+        match collapse {
+            Collapse::Cluster => self.cluster_id,
+            Collapse::Host => self.crawl_host,
+        }
+    }
+
+    /// Like `search`, but only yields the best recipe of each group
+    /// of recipes as told by `collapse`
+    ///
+    /// Fails if the index was built before `collapse` was supported
+    pub fn search_collapsed(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+        collapse: Collapse,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let group = self.collapse_field(collapse).ok_or_else(|| {
+            TantivyError::SchemaError(format!(
+                "Collapsing by {:?} needs the index to be rebuilt",
+                collapse
+            ))
+        })?;
+
+        self.collect(searcher, query, limit, sort, after, Some(group))
+    }
+
+    fn collect(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+        group: Option<Field>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        macro_rules! render {
+            ($type: ty, $order:ident, $condition:expr $(, $field:ident)?) => {
+                if let Some(group) = group {
                     let top_collector =
-                        TopCollector::<$type, $order, _>::new(limit, after.as_paginator(self.id))
-                            .top_fast_field(self.features.$field);
+                        CollapsingTopCollector::<$type, $order, _, _>::new(limit, $condition, group)
+                            $(.top_fast_field(self.features.$field))?;
 
                     self.render::<$type, _>(&searcher, query, top_collector)
                 } else {
-                    let top_collector = TopCollector::<$type, $order, _>::new(limit, true)
-                        .top_fast_field(self.features.$field);
+                    let top_collector = TopCollector::<$type, $order, _>::new(limit, $condition)
+                        $(.top_fast_field(self.features.$field))?;
 
                     self.render::<$type, _>(&searcher, query, top_collector)
                 }
             };
+        }
 
-            ($order:ident) => {
+        macro_rules! collect {
+            ($type: ty, $field:ident, $order:ident) => {
                 if let Some(after) = after {
-                    let top_collector =
-                        TopCollector::<_, $order, _>::new(limit, after.as_paginator(self.id));
-
-                    self.render::<Score, _>(&searcher, query, top_collector)
+                    render!($type, $order, after.as_paginator(self.id), $field)
                 } else {
-                    let top_collector = TopCollector::<_, $order, _>::new(limit, true);
+                    render!($type, $order, true, $field)
+                }
+            };
 
-                    self.render::<Score, _>(&searcher, query, top_collector)
+            ($order:ident) => {
+                if let Some(after) = after {
+                    render!(Score, $order, after.as_paginator(self.id))
+                } else {
+                    render!(Score, $order, true)
                 }
             };
         }
@@ -128,6 +223,27 @@ impl RecipeIndex {
         }
     }
 
+    /// Where the recipe identified by `recipe_id` is in the index,
+    /// if anywhere
+    pub fn find_recipe(&self, searcher: &Searcher, recipe_id: RecipeId) -> Option<DocAddress> {
+        // Ids aren't indexed, but a linear scan is fine for the odd
+        // recipe lookup
+        searcher
+            .segment_readers()
+            .iter()
+            .enumerate()
+            .find_map(|(segment_ord, reader)| {
+                let ids = reader
+                    .fast_fields()
+                    .u64(self.id)
+                    .expect("id field is indexed with the FAST flag");
+
+                (0..reader.max_doc())
+                    .find(|&doc| !reader.is_deleted(doc) && ids.get(doc) == recipe_id)
+                    .map(|doc| DocAddress(segment_ord as SegmentLocalId, doc))
+            })
+    }
+
     pub fn aggregate_features(
         &self,
         searcher: &Searcher,
@@ -156,9 +272,18 @@ impl RecipeIndex {
     ) -> Result<(usize, Vec<RecipeId>, Option<After>)>
     where
         T: 'static + Sync + Send + Copy + AsAfter,
-        C: Collector<Fruit = CollectionResult<T>>,
+        C: Collector,
+        C::Fruit: Into<CollectionResult<T>>,
     {
-        let result = searcher.search(query, &collector)?;
+        let result: CollectionResult<T> = searcher.search(query, &collector)?.into();
+        self.render_result(searcher, result)
+    }
+
+    fn render_result<T: AsAfter>(
+        &self,
+        searcher: &Searcher,
+        result: CollectionResult<T>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
         let mut recipe_ids = Vec::with_capacity(result.items.len());
 
         let has_next = result.has_next();
@@ -187,10 +312,29 @@ impl RecipeIndex {
     }
 }
 
+/// A stable hash of the host part of the given url, without
+/// the "www." prefix
+fn hash_host(url: &str) -> u64 {
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    let host = without_scheme
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    let host = host.trim_start_matches("www.");
+
+    // FNV-1a, so that values stay the same across builds
+    host.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 impl From<&mut SchemaBuilder> for RecipeIndex {
     fn from(builder: &mut SchemaBuilder) -> Self {
         RecipeIndex {
             id: builder.add_u64_field(FIELD_ID, STORED | FAST),
+            cluster_id: Some(builder.add_u64_field(FIELD_CLUSTER_ID, INDEXED | FAST)),
+            crawl_host: Some(builder.add_u64_field(FIELD_CRAWL_HOST, INDEXED | FAST)),
 
             name: builder.add_text_field(FIELD_NAME, TEXT),
             ingredients: builder.add_text_field(FIELD_INGREDIENTS, TEXT),
@@ -214,6 +358,9 @@ impl TryFrom<&Schema> for RecipeIndex {
 
         Ok(RecipeIndex {
             id: get_field(FIELD_ID)?,
+            // Older indices can still be searched, just not collapsed
+            cluster_id: schema.get_field(FIELD_CLUSTER_ID),
+            crawl_host: schema.get_field(FIELD_CRAWL_HOST),
 
             name: get_field(FIELD_NAME)?,
             ingredients: get_field(FIELD_INGREDIENTS)?,
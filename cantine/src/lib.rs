@@ -1,3 +1,7 @@
 pub mod database;
+pub mod dedup;
+pub mod flags;
 pub mod index;
 pub mod model;
+pub mod querylog;
+pub mod search;
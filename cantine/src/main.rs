@@ -1,29 +1,32 @@
-use std::{convert::TryFrom, env, io, path::Path, str::FromStr, sync::Arc};
+use std::{
+    env, io,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use env_logger;
-use serde::Serialize;
-use tique::QueryParser;
 use uuid::Uuid;
 
 use actix_web::{
     http::StatusCode, middleware::Logger, web, App, HttpResponse, HttpServer, Result as ActixResult,
 };
 
-use tantivy::{
-    query::{AllQuery, BooleanQuery, Occur, Query},
-    Index, IndexReader, Result,
-};
+use tantivy::{Index, Result};
 
 use cantine::{
     database::DatabaseReader,
-    index::{After, RecipeIndex},
+    index::After,
     model::{
-        FeaturesAggregationQuery, FeaturesAggregationResult, Recipe, RecipeCard, RecipeId,
-        RecipeInfo, SearchCursor, SearchQuery, SearchResult, Sort,
+        ExplainQuery, Recipe, RecipeCard, RecipeInfo, SearchCursor, SearchQuery, SearchResult,
     },
+    querylog::{LoggedQuery, QueryLog},
+    search::{cursor_to_after, ExecuteResult, IndexInfo, SearchConfig, SearchState},
 };
 
 type RecipeDatabase = Arc<DatabaseReader<Recipe>>;
+type SharedQueryLog = Option<Arc<Mutex<QueryLog>>>;
 
 pub async fn recipe(
     database: web::Data<RecipeDatabase>,
@@ -40,31 +43,15 @@ pub async fn recipe(
     }
 }
 
-#[derive(Serialize, Clone)]
-pub struct IndexInfo {
-    pub total_recipes: u64,
-    pub features: FeaturesAggregationResult,
-    pub sort: Vec<Sort>,
-}
-
 pub async fn index_info(info: web::Data<IndexInfo>) -> ActixResult<HttpResponse> {
     Ok(HttpResponse::Ok().json(info.get_ref()))
 }
 
-fn cursor_to_after(database: &RecipeDatabase, cursor: &SearchCursor) -> Option<After> {
-    database
-        .id_for_uuid(&Uuid::from_bytes(*cursor.uuid()))
-        .map(|id| match &cursor {
-            SearchCursor::Relevance(score, _) => After::Relevance(*score, *id),
-            SearchCursor::U64Field(score, _) => After::U64Field(*score, *id),
-            SearchCursor::F64Field(score, _) => After::F64Field(*score, *id),
-        })
-}
-
 pub async fn search(
     query: web::Json<SearchQuery>,
     state: web::Data<Arc<SearchState>>,
     database: web::Data<RecipeDatabase>,
+    query_log: web::Data<SharedQueryLog>,
 ) -> ActixResult<HttpResponse> {
     let after = if let Some(cursor) = &query.after {
         let checked_after = cursor_to_after(&database, &cursor);
@@ -76,8 +63,46 @@ pub async fn search(
         None
     };
 
-    let (total_found, recipe_ids, after, agg) =
-        web::block(move || -> Result<ExecuteResult> { state.search(query.0, after) }).await?;
+    if let Err(exceeded) = state.check_limits(&query) {
+        return Ok(HttpResponse::BadRequest().body(exceeded.to_string()));
+    }
+
+    if let Some(collapse) = query.collapse {
+        if !state.can_collapse(collapse) {
+            return Ok(HttpResponse::BadRequest().body("Collapsing is unavailable for this index"));
+        }
+    }
+
+    let query_log = query_log.get_ref().clone();
+    let ((total_found, recipe_ids, after, agg), suggestion) =
+        web::block(move || -> Result<(ExecuteResult, Option<String>)> {
+            let logged_query = query_log.as_ref().map(|_| query.0.clone());
+            let fulltext = query.fulltext.clone();
+            let match_words = query.match_words;
+
+            let start = Instant::now();
+            let result = state.search(query.0, after)?;
+            let elapsed_micros = start.elapsed().as_micros() as u64;
+
+            if let (Some(log), Some(logged_query)) = (query_log, logged_query) {
+                let normalized = logged_query
+                    .fulltext
+                    .as_ref()
+                    .map(|fulltext| state.normalize(fulltext, logged_query.match_words));
+                let mut entry =
+                    LoggedQuery::new(logged_query, elapsed_micros, result.0, result.1.clone());
+                entry.normalized = normalized;
+                if let Err(err) = log.lock().expect("lock never poisoned").append(&entry) {
+                    log::warn!("Failure writing to the query log: {}", err);
+                }
+            }
+
+            let suggestion =
+                fulltext.and_then(|fulltext| state.suggest(&fulltext, match_words, result.0));
+
+            Ok((result, suggestion))
+        })
+        .await?;
 
     let num_results = recipe_ids.len();
     let mut items = Vec::with_capacity(num_results);
@@ -103,95 +128,39 @@ pub async fn search(
         items,
         next,
         agg,
+        suggestion,
     }))
 }
 
-type ExecuteResult = (
-    usize,
-    Vec<RecipeId>,
-    Option<After>,
-    Option<FeaturesAggregationResult>,
-);
-
-pub struct SearchState {
-    reader: IndexReader,
-    recipe_index: RecipeIndex,
-    query_parser: QueryParser,
-    agg_threshold: usize,
-}
-
-impl SearchState {
-    pub fn search(&self, query: SearchQuery, after: Option<After>) -> Result<ExecuteResult> {
-        let limit = query.num_items.unwrap_or(10) as usize;
-
-        let searcher = self.reader.searcher();
-        let interpreted_query = self.interpret_query(&query)?;
-
-        let (total_found, recipe_ids, after) = self.recipe_index.search(
-            &searcher,
-            &interpreted_query,
-            limit,
-            query.sort.unwrap_or(Sort::Relevance),
-            after,
-        )?;
-
-        let agg = if total_found <= self.agg_threshold {
-            query
-                .agg
-                .map(|agg_query| {
-                    self.recipe_index
-                        .aggregate_features(&searcher, &interpreted_query, agg_query)
-                })
-                .transpose()?
-        } else {
-            None
-        };
-
-        Ok((total_found, recipe_ids, after, agg))
-    }
-
-    fn interpret_query(&self, query: &SearchQuery) -> Result<Box<dyn Query>> {
-        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-
-        if let Some(fulltext) = &query.fulltext {
-            if let Some(parsed) = self.query_parser.parse_dixmax(fulltext.as_str(), 0.1) {
-                subqueries.push((Occur::Must, parsed));
-            }
-        }
-
-        if let Some(filter) = &query.filter {
-            for query in self.recipe_index.features.interpret(filter).into_iter() {
-                subqueries.push((Occur::Must, query));
-            }
-        }
+pub async fn explain(
+    query: web::Json<ExplainQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let recipe_id = match database.id_for_uuid(&query.uuid) {
+        Some(&recipe_id) => recipe_id,
+        None => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+    };
 
-        match subqueries.len() {
-            0 => Ok(Box::new(AllQuery)),
-            1 => Ok(subqueries.pop().expect("length has been checked").1),
-            _ => Ok(Box::new(BooleanQuery::from(subqueries))),
-        }
+    if let Err(exceeded) = state.check_limits(&query.query) {
+        return Ok(HttpResponse::BadRequest().body(exceeded.to_string()));
     }
 
-    pub fn index_info(&self) -> Result<IndexInfo> {
-        let searcher = self.reader.searcher();
-        let features = self.recipe_index.aggregate_features(
-            &searcher,
-            &AllQuery,
-            FeaturesAggregationQuery::full_range(),
-        )?;
-
-        let sort = Sort::VALUES.to_vec();
+    let explanation = web::block(move || state.explain(&query.query, recipe_id)).await?;
 
-        Ok(IndexInfo {
-            total_recipes: searcher.num_docs(),
-            features,
-            sort,
-        })
+    if let Some(explanation) = explanation {
+        Ok(HttpResponse::Ok().json(explanation))
+    } else {
+        Ok(HttpResponse::NotFound().body("Recipe not found by the search"))
     }
 }
 
 const BASE_DIR: &str = "BASE_DIR";
 const AGG_THRESHOLD: &str = "AGG_THRESHOLD";
+const SEARCH_CONFIG: &str = "SEARCH_CONFIG";
+const QUERY_LOG: &str = "QUERY_LOG";
+const QUERY_LOG_MAX_SIZE: &str = "QUERY_LOG_MAX_SIZE";
+const QUERY_LOG_MAX_FILES: &str = "QUERY_LOG_MAX_FILES";
 
 fn get_env(key: &str) -> Result<String> {
     env::var(key).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, key).into())
@@ -216,44 +185,58 @@ async fn main() -> Result<()> {
     let index_path = base_path.join("tantivy");
     let db_path = base_path.join("database");
 
+    let config = match get_env(SEARCH_CONFIG) {
+        Ok(path) => SearchConfig::load(&path)?,
+        Err(_) => SearchConfig::default(),
+    };
+    log::info!("Using {:?}", config);
+
     let index = Index::open_in_dir(&index_path)?;
-    let recipe_index = RecipeIndex::try_from(&index.schema())?;
-    let mut query_parser = QueryParser::new(
+    let search_state = Arc::new(SearchState::new(
         &index,
-        vec![
-            recipe_index.name,
-            recipe_index.ingredients,
-            recipe_index.instructions,
-        ],
-    )?;
-
-    // XXX This is as scientific as "4" is random
-    // Reduce importance of instructions match
-    query_parser.set_boost(recipe_index.instructions, Some(0.7));
-    // And make name matches slightly more important than ingredient
-    query_parser.set_boost(recipe_index.name, Some(1.15));
-
-    let reader = index.reader()?;
-    let search_state = Arc::new(SearchState {
-        reader,
-        recipe_index,
-        query_parser,
-        agg_threshold: threshold.unwrap_or(std::usize::MAX),
-    });
+        &config,
+        threshold.unwrap_or(std::usize::MAX),
+    )?);
 
     let database: RecipeDatabase = Arc::new(DatabaseReader::open(&db_path)?);
 
     let info = search_state.index_info()?;
 
+    let query_log: SharedQueryLog = match get_env(QUERY_LOG) {
+        Ok(path) => {
+            let max_size = get_env(QUERY_LOG_MAX_SIZE)
+                .ok()
+                .map(|v| u64::from_str(&v).expect("valid u64"))
+                .unwrap_or(100 * 1024 * 1024);
+            let max_files = get_env(QUERY_LOG_MAX_FILES)
+                .ok()
+                .map(|v| usize::from_str(&v).expect("valid usize"))
+                .unwrap_or(5);
+
+            log::info!(
+                "Logging queries to {} max_size={} max_files={}",
+                path,
+                max_size,
+                max_files
+            );
+            Some(Arc::new(Mutex::new(QueryLog::open(
+                &path, max_size, max_files,
+            )?)))
+        }
+        Err(_) => None,
+    };
+
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(search_state.clone()))
             .app_data(web::Data::new(database.clone()))
             .app_data(web::Data::new(info.clone()))
+            .app_data(web::Data::new(query_log.clone()))
             .data(web::JsonConfig::default().limit(4096))
             .service(web::resource("/recipe/{uuid}").route(web::get().to(recipe)))
             .service(web::resource("/search").route(web::post().to(search)))
+            .service(web::resource("/explain").route(web::post().to(explain)))
             .service(web::resource("/info").route(web::get().to(index_info)))
     })
     .bind("127.0.0.1:8080")?
@@ -6,6 +6,7 @@ use serde::{
     Deserialize, Serialize, Serializer,
 };
 use tantivy::Score;
+use tique::MinimumShouldMatch;
 use uuid::{self, Uuid};
 
 use crate::database::DatabaseRecord;
@@ -185,7 +186,7 @@ impl Sort {
     ];
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct SearchQuery {
     pub fulltext: Option<String>,
@@ -197,6 +198,45 @@ pub struct SearchQuery {
     pub sort: Option<Sort>,
     #[serde(default)]
     pub ascending: bool,
+
+    pub collapse: Option<Collapse>,
+
+    /// How many of the optional words in the fulltext must match,
+    /// like `3` or `"75%"`
+    pub minimum_should_match: Option<MinimumShouldMatch>,
+
+    /// Whether words in the fulltext without a `+` or `-` are
+    /// required. Defaults to `any`
+    #[serde(rename = "match")]
+    pub match_words: Option<Match>,
+}
+
+/// Asks how a search scores the recipe identified by `uuid`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExplainQuery {
+    pub query: SearchQuery,
+    pub uuid: Uuid,
+}
+
+/// Which of the words in the fulltext a recipe must have
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Match {
+    /// At least one of them
+    Any,
+    /// Every one of them
+    All,
+}
+
+/// Keep only the best recipe out of every group of results
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Collapse {
+    /// Recipes found to be duplicates of each other when loading
+    Cluster,
+    /// Recipes crawled from the same host
+    Host,
 }
 
 #[derive(Serialize, Debug, Default)]
@@ -209,9 +249,13 @@ pub struct SearchResult {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next: Option<SearchCursor>,
+
+    /// A corrected fulltext query, for searches that found little
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum SearchCursor {
     F64Field(f64, uuid::Bytes),
     U64Field(u64, uuid::Bytes),
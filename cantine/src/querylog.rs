@@ -0,0 +1,190 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{RecipeId, SearchQuery};
+
+/// A search as seen by the server, along with what came out of it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoggedQuery {
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub query: SearchQuery,
+    /// The fulltext query in canonical form, so that different ways
+    /// of writing the same search can be grouped together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<String>,
+    /// How long executing the search took, ignoring everything
+    /// http-related
+    pub elapsed_micros: u64,
+    pub total_found: usize,
+    pub recipe_ids: Vec<RecipeId>,
+}
+
+impl LoggedQuery {
+    pub fn new(
+        query: SearchQuery,
+        elapsed_micros: u64,
+        total_found: usize,
+        recipe_ids: Vec<RecipeId>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            timestamp,
+            query,
+            normalized: None,
+            elapsed_micros,
+            total_found,
+            recipe_ids,
+        }
+    }
+}
+
+/// An append-only JSON-lines log of queries
+///
+/// Once the log grows past `max_size` bytes it gets renamed to
+/// `{path}.1` (and a previous `{path}.1` to `{path}.2` and so on)
+/// and a fresh one is started. At most `max_files` of these old
+/// logs are kept around.
+pub struct QueryLog {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+
+    file: File,
+    size: u64,
+}
+
+impl QueryLog {
+    pub fn open<P: AsRef<Path>>(path: P, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    pub fn append(&mut self, entry: &LoggedQuery) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    fn rotated_path(&self, num: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", num));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for num in (1..self.max_files).rev() {
+                let older = self.rotated_path(num);
+                if older.exists() {
+                    fs::rename(&older, self.rotated_path(num + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use tempfile;
+
+    fn entry(fulltext: &str) -> LoggedQuery {
+        let query = SearchQuery {
+            fulltext: Some(fulltext.to_string()),
+            ..SearchQuery::default()
+        };
+        LoggedQuery::new(query, 42, 1, vec![7])
+    }
+
+    fn read_log(path: &Path) -> Vec<String> {
+        BufReader::new(File::open(path).unwrap())
+            .lines()
+            .map(|line| {
+                let logged: LoggedQuery = serde_json::from_str(&line.unwrap()).unwrap();
+                logged.query.fulltext.unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn appends_json_lines() -> io::Result<()> {
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = tmpdir.path().join("queries.log");
+
+        let mut log = QueryLog::open(&path, 1 << 20, 2)?;
+        log.append(&entry("bacon"))?;
+        log.append(&entry("eggs"))?;
+        drop(log);
+
+        // Reopening keeps appending
+        let mut log = QueryLog::open(&path, 1 << 20, 2)?;
+        log.append(&entry("spam"))?;
+
+        assert_eq!(vec!["bacon", "eggs", "spam"], read_log(&path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rotation() -> io::Result<()> {
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = tmpdir.path().join("queries.log");
+
+        // Any entry is larger than a single byte, so every append
+        // leads to a rotation
+        let mut log = QueryLog::open(&path, 1, 2)?;
+        for fulltext in &["a", "b", "c", "d"] {
+            log.append(&entry(fulltext))?;
+        }
+
+        assert!(read_log(&path).is_empty());
+        assert_eq!(vec!["d"], read_log(&log.rotated_path(1)));
+        assert_eq!(vec!["c"], read_log(&log.rotated_path(2)));
+        assert!(!log.rotated_path(3).exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    convert::TryFrom,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    query::{AllQuery, BooleanQuery, Explanation, Occur, Query},
+    DocSet, Index, IndexReader, Result, SkipResult,
+};
+
+use tique::{suggest::Suggester, Clause, LimitExceeded, QueryLimits, QueryParser, Synonyms};
+use uuid::Uuid;
+
+use crate::{
+    database::DatabaseReader,
+    index::{After, RecipeIndex},
+    model::{
+        Collapse, FeaturesAggregationQuery, FeaturesAggregationResult, Match, Recipe, RecipeId,
+        SearchCursor, SearchQuery, Sort,
+    },
+};
+
+/// Knobs that change how the full text of a search is interpreted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    pub name_boost: Option<f32>,
+    pub ingredients_boost: Option<f32>,
+    pub instructions_boost: Option<f32>,
+    /// Use a `DisMaxQuery` with this tiebreaker instead of a plain
+    /// boolean one when set
+    pub dismax_tiebreaker: Option<f32>,
+    /// Maximum edit distance (up to 2) for matching words that aren't
+    /// found anywhere in the index
+    pub auto_fuzzy: Option<u8>,
+    /// Maximum number of words a pattern like `choco*` may match,
+    /// using the parser's default when not set
+    pub max_expansions: Option<usize>,
+    /// How far apart the words of a phrase may be when the query
+    /// doesn't say, like `"chicken thighs"~2` does
+    pub phrase_slop: Option<u32>,
+    /// A synonyms file (see `tique::Synonyms::from_reader`) used to
+    /// expand words in the name, ingredients and instructions
+    pub synonyms: Option<PathBuf>,
+    /// Importance of synonyms compared to the words in the query,
+    /// using the parser's default when not set
+    pub synonym_boost: Option<f32>,
+    /// Suggest a corrected fulltext query for searches that find
+    /// fewer recipes than this. Disabled when not set
+    pub suggestion_threshold: Option<usize>,
+    /// Bounds on how expensive the fulltext of a search may get
+    pub query_limits: QueryLimits,
+    /// Reject searches that go over `query_limits` instead of only
+    /// searching for the part of the fulltext within them
+    pub reject_expensive_queries: bool,
+    /// Count every recipe found by relevance-sorted searches up to
+    /// this many, skipping the ones that can't make it to the top
+    /// past that, which turns the reported total into a lower bound.
+    /// Never less than what aggregations and suggestions need.
+    /// Disabled when not set, which is the default
+    pub exact_total: Option<usize>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            // XXX This is as scientific as "4" is random
+            // Make name matches slightly more important than ingredient
+            name_boost: Some(1.15),
+            ingredients_boost: None,
+            // Reduce importance of instructions match
+            instructions_boost: Some(0.7),
+            dismax_tiebreaker: Some(0.1),
+            auto_fuzzy: None,
+            max_expansions: None,
+            phrase_slop: None,
+            synonyms: None,
+            synonym_boost: None,
+            suggestion_threshold: Some(5),
+            query_limits: QueryLimits {
+                max_input_length: Some(500),
+                max_clauses: Some(32),
+                max_term_queries: Some(200),
+                max_phrase_length: Some(16),
+            },
+            reject_expensive_queries: false,
+            exact_total: None,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Reads a configuration from a JSON file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?)
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct IndexInfo {
+    pub total_recipes: u64,
+    pub features: FeaturesAggregationResult,
+    pub sort: Vec<Sort>,
+}
+
+pub type ExecuteResult = (
+    usize,
+    Vec<RecipeId>,
+    Option<After>,
+    Option<FeaturesAggregationResult>,
+);
+
+/// Turns a cursor from the API into one the index understands. Yields
+/// None if the cursor points at a recipe that doesn't exist
+pub fn cursor_to_after(database: &DatabaseReader<Recipe>, cursor: &SearchCursor) -> Option<After> {
+    database
+        .id_for_uuid(&Uuid::from_bytes(*cursor.uuid()))
+        .map(|id| match &cursor {
+            SearchCursor::Relevance(score, _) => After::Relevance(*score, *id),
+            SearchCursor::U64Field(score, _) => After::U64Field(*score, *id),
+            SearchCursor::F64Field(score, _) => After::F64Field(*score, *id),
+        })
+}
+
+pub struct SearchState {
+    reader: IndexReader,
+    recipe_index: RecipeIndex,
+    query_parser: QueryParser,
+    /// Same as `query_parser`, but requiring every word by default
+    match_all_parser: QueryParser,
+    suggester: Suggester,
+    suggestion_threshold: Option<usize>,
+    reject_expensive_queries: bool,
+    dismax_tiebreaker: Option<f32>,
+    agg_threshold: usize,
+    exact_total: Option<usize>,
+}
+
+impl SearchState {
+    pub fn new(index: &Index, config: &SearchConfig, agg_threshold: usize) -> Result<Self> {
+        let recipe_index = RecipeIndex::try_from(&index.schema())?;
+        let query_parser = create_query_parser(index, &recipe_index, config)?;
+        let mut match_all_parser = create_query_parser(index, &recipe_index, config)?;
+        match_all_parser.set_default_occur(Occur::Must);
+
+        let suggester = Suggester::new(
+            index,
+            vec![
+                recipe_index.name,
+                recipe_index.ingredients,
+                recipe_index.instructions,
+            ],
+        )?;
+
+        Ok(Self {
+            reader: index.reader()?,
+            recipe_index,
+            query_parser,
+            match_all_parser,
+            suggester,
+            suggestion_threshold: config.suggestion_threshold,
+            reject_expensive_queries: config.reject_expensive_queries,
+            dismax_tiebreaker: config.dismax_tiebreaker,
+            agg_threshold,
+            // Aggregations and suggestions depend on the total being
+            // right up to their thresholds
+            exact_total: config.exact_total.map(|exact_total| {
+                exact_total
+                    .max(agg_threshold)
+                    .max(config.suggestion_threshold.unwrap_or(0))
+            }),
+        })
+    }
+
+    pub fn search(&self, query: SearchQuery, after: Option<After>) -> Result<ExecuteResult> {
+        let limit = query.num_items.unwrap_or(10) as usize;
+
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(&query)?;
+
+        let sort = query.sort.unwrap_or(Sort::Relevance);
+        let (total_found, recipe_ids, after) = if let Some(collapse) = query.collapse {
+            self.recipe_index.search_collapsed(
+                &searcher,
+                &interpreted_query,
+                limit,
+                sort,
+                after,
+                collapse,
+            )?
+        } else if let (Sort::Relevance, Some(exact_total)) = (&sort, self.exact_total) {
+            self.recipe_index.search_pruned(
+                &searcher,
+                &interpreted_query,
+                limit,
+                after,
+                exact_total,
+            )?
+        } else {
+            self.recipe_index
+                .search(&searcher, &interpreted_query, limit, sort, after)?
+        };
+
+        let agg = if total_found <= self.agg_threshold {
+            query
+                .agg
+                .map(|agg_query| {
+                    self.recipe_index
+                        .aggregate_features(&searcher, &interpreted_query, agg_query)
+                })
+                .transpose()?
+        } else {
+            None
+        };
+
+        Ok((total_found, recipe_ids, after, agg))
+    }
+
+    fn parser_for(&self, match_words: Option<Match>) -> &QueryParser {
+        if match_words == Some(Match::All) {
+            &self.match_all_parser
+        } else {
+            &self.query_parser
+        }
+    }
+
+    fn interpret_query(&self, query: &SearchQuery) -> Result<Box<dyn Query>> {
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
+        if let Some(fulltext) = &query.fulltext {
+            let parsed = self
+                .parser_for(query.match_words)
+                .parse_with_minimum_should_match(
+                    fulltext.as_str(),
+                    query.minimum_should_match,
+                    self.dismax_tiebreaker,
+                );
+
+            if let Some(parsed) = parsed {
+                subqueries.push((Occur::Must, parsed));
+            }
+        }
+
+        if let Some(filter) = &query.filter {
+            for query in self.recipe_index.features.interpret(filter).into_iter() {
+                subqueries.push((Occur::Must, query));
+            }
+        }
+
+        match subqueries.len() {
+            0 => Ok(Box::new(AllQuery)),
+            1 => Ok(subqueries.pop().expect("length has been checked").1),
+            _ => Ok(Box::new(BooleanQuery::from(subqueries))),
+        }
+    }
+
+    /// Whether the index can collapse results as `collapse` says,
+    /// which indices built before collapsing was supported can't
+    pub fn can_collapse(&self, collapse: Collapse) -> bool {
+        self.recipe_index.collapse_field(collapse).is_some()
+    }
+
+    /// Checks whether the fulltext of `query` may be searched for,
+    /// which is always the case unless configured to reject queries
+    /// that go over the limits
+    pub fn check_limits(&self, query: &SearchQuery) -> std::result::Result<(), LimitExceeded> {
+        match &query.fulltext {
+            Some(fulltext) if self.reject_expensive_queries => {
+                // Both parsers share the same limits
+                self.query_parser.check_limits(fulltext)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Renders a fulltext query in the canonical form the parser
+    /// for `match_words` understands it as
+    pub fn normalize(&self, fulltext: &str, match_words: Option<Match>) -> String {
+        self.parser_for(match_words).parse_ast(fulltext).to_string()
+    }
+
+    /// A corrected version of `fulltext`, if its search found fewer
+    /// recipes than the configured threshold and there's anything
+    /// to correct. It means the same as `fulltext` when searched
+    /// with the same `match_words`
+    pub fn suggest(
+        &self,
+        fulltext: &str,
+        match_words: Option<Match>,
+        total_found: usize,
+    ) -> Option<String> {
+        if total_found >= self.suggestion_threshold? {
+            return None;
+        }
+
+        let mut ast = self.parser_for(match_words).parse_ast(fulltext);
+        if self.correct(&mut ast.clauses) {
+            Some(ast.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Corrects the text of every word and phrase, leaving
+    /// everything else as is. Yields whether anything changed
+    fn correct(&self, clauses: &mut [Clause]) -> bool {
+        let mut changed = false;
+
+        for clause in clauses {
+            match clause {
+                Clause::Term { text, .. } | Clause::Phrase { text, .. } => {
+                    if let Some(suggestion) = self.suggester.suggest(text) {
+                        *text = suggestion;
+                        changed = true;
+                    }
+                }
+                Clause::Group { clauses, .. } => changed |= self.correct(clauses),
+                Clause::Wildcard { .. } | Clause::Range { .. } => {}
+            }
+        }
+
+        changed
+    }
+
+    /// How the given search scores the recipe identified by
+    /// `recipe_id`. Yields None if the search doesn't find it
+    pub fn explain(&self, query: &SearchQuery, recipe_id: RecipeId) -> Result<Option<Explanation>> {
+        let searcher = self.reader.searcher();
+        let address = match self.recipe_index.find_recipe(&searcher, recipe_id) {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        let interpreted_query = self.interpret_query(query)?;
+        let weight = interpreted_query.weight(&searcher, true)?;
+
+        let reader = searcher.segment_reader(address.segment_ord());
+        if weight.scorer(reader, 1.0)?.skip_next(address.doc()) != SkipResult::Reached {
+            return Ok(None);
+        }
+
+        Ok(Some(weight.explain(reader, address.doc())?))
+    }
+
+    pub fn index_info(&self) -> Result<IndexInfo> {
+        let searcher = self.reader.searcher();
+        let features = self.recipe_index.aggregate_features(
+            &searcher,
+            &AllQuery,
+            FeaturesAggregationQuery::full_range(),
+        )?;
+
+        let sort = Sort::VALUES.to_vec();
+
+        Ok(IndexInfo {
+            total_recipes: searcher.num_docs(),
+            features,
+            sort,
+        })
+    }
+}
+
+/// A parser for the fulltext of searches, configured as `config` says
+fn create_query_parser(
+    index: &Index,
+    recipe_index: &RecipeIndex,
+    config: &SearchConfig,
+) -> Result<QueryParser> {
+    let mut query_parser = QueryParser::new(
+        index,
+        vec![
+            recipe_index.name,
+            recipe_index.ingredients,
+            recipe_index.instructions,
+        ],
+    )?;
+
+    query_parser.set_boost(recipe_index.name, config.name_boost);
+    query_parser.set_boost(recipe_index.ingredients, config.ingredients_boost);
+    query_parser.set_boost(recipe_index.instructions, config.instructions_boost);
+    query_parser.set_auto_fuzzy(config.auto_fuzzy);
+    query_parser.set_limits(config.query_limits);
+    if let Some(max_expansions) = config.max_expansions {
+        query_parser.set_max_expansions(max_expansions);
+    }
+    if let Some(slop) = config.phrase_slop {
+        query_parser.set_default_slop(slop);
+    }
+    if let Some(path) = &config.synonyms {
+        let synonyms = Synonyms::load(path)?;
+        query_parser.set_synonyms(recipe_index.name, Some(&synonyms));
+        query_parser.set_synonyms(recipe_index.ingredients, Some(&synonyms));
+        query_parser.set_synonyms(recipe_index.instructions, Some(&synonyms));
+    }
+    if let Some(boost) = config.synonym_boost {
+        query_parser.set_synonym_boost(boost);
+    }
+
+    // Every filter can also be written as part of the fulltext,
+    // like `calories:<400`
+    for (name, field) in recipe_index.features.fields() {
+        query_parser.add_range_field(name.to_owned(), field)?;
+    }
+
+    Ok(query_parser)
+}
@@ -3,6 +3,7 @@ use serde_json;
 use once_cell::sync::Lazy;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use tantivy::{
     query::{AllQuery, RangeQuery},
     schema::SchemaBuilder,
@@ -10,11 +11,12 @@ use tantivy::{
 };
 
 use cantine::{
-    index::RecipeIndex,
-    model::{Recipe, RecipeId, Sort},
+    index::{After, RecipeIndex},
+    model::{Collapse, Match, Recipe, RecipeId, SearchQuery, Sort},
+    search::{SearchConfig, SearchState},
 };
 
-use tique::QueryParser;
+use tique::{LimitExceeded, QueryParser};
 
 struct GlobalData {
     index: Index,
@@ -87,6 +89,81 @@ fn pagination_works() -> Result<()> {
     Ok(())
 }
 
+fn crawl_host(recipe: &Recipe) -> &str {
+    recipe
+        .crawl_url
+        .split('/')
+        .nth(2)
+        .unwrap()
+        .trim_start_matches("www.")
+}
+
+#[test]
+fn collapsed_pagination_yields_each_host_once() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let hosts = GLOBAL.db.values().map(crawl_host).collect::<HashSet<_>>();
+    assert!(hosts.len() < INDEX_SIZE);
+
+    for sort in &[
+        Sort::Relevance,
+        Sort::NumIngredients,
+        Sort::InstructionsLengthAsc,
+    ] {
+        let mut after = None;
+        let mut seen = HashSet::with_capacity(hosts.len());
+
+        loop {
+            let (total, found_ids, next) = GLOBAL.cantine.search_collapsed(
+                &searcher,
+                &AllQuery,
+                10,
+                sort.clone(),
+                after,
+                Collapse::Host,
+            )?;
+
+            assert_eq!(INDEX_SIZE, total);
+
+            for id in found_ids {
+                let host = crawl_host(GLOBAL.db.get(&id).unwrap());
+                assert!(seen.insert(host), "Host {} seen twice", host);
+            }
+
+            if let Some(new_after) = next {
+                after = Some(new_after);
+            } else {
+                break;
+            }
+        }
+
+        assert_eq!(hosts, seen);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn collapsing_by_cluster_is_a_noop_without_duplicates() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (total, found_ids, _next) = GLOBAL.cantine.search_collapsed(
+        &searcher,
+        &AllQuery,
+        INDEX_SIZE,
+        Sort::TotalTime,
+        None,
+        Collapse::Cluster,
+    )?;
+
+    assert_eq!(INDEX_SIZE, total);
+    assert_eq!(INDEX_SIZE, found_ids.len());
+
+    Ok(())
+}
+
 #[test]
 fn num_ingredients_sort() -> Result<()> {
     let reader = GLOBAL.index.reader()?;
@@ -327,3 +404,352 @@ fn ascending_sort_works_for_relevance() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn fulltext_ranges_match_filters() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let search = |fulltext: &str, filter: Option<&str>| -> Result<_> {
+        let query = SearchQuery {
+            fulltext: Some(fulltext.to_owned()),
+            filter: filter.map(|json| serde_json::from_str(json).expect("valid filter")),
+            num_items: Some(100),
+            ..SearchQuery::default()
+        };
+        let (total, found_ids, _after, _agg) = state.search(query, None)?;
+        Ok((total, found_ids))
+    };
+
+    for (fulltext, bare, filter) in &[
+        (
+            "potato calories:..400",
+            "potato",
+            r#"{"calories": {"start": 0, "end": 400}}"#,
+        ),
+        (
+            "cheese total_time:10..30 num_ingredients:5..10",
+            "cheese",
+            r#"{"total_time": {"start": 10, "end": 30},
+                "num_ingredients": {"start": 5, "end": 10}}"#,
+        ),
+        (
+            "+chicken fat_content:1.5..20",
+            "+chicken",
+            r#"{"fat_content": {"start": 1.5, "end": 20}}"#,
+        ),
+    ] {
+        let (total, found_ids) = search(fulltext, None)?;
+        assert!(total > 0, "[{}] found nothing", fulltext);
+        assert_eq!(
+            (total, found_ids),
+            search(bare, Some(filter))?,
+            "[{}] differs from its filter",
+            fulltext
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn synonyms_widen_fulltext_searches() -> Result<()> {
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = tmpdir.path().join("synonyms.txt");
+    std::fs::write(&path, "potato, spud\n")?;
+
+    let search = |config: &SearchConfig, fulltext: &str| -> Result<usize> {
+        let state = SearchState::new(&GLOBAL.index, config, usize::MAX)?;
+        let query = SearchQuery {
+            fulltext: Some(fulltext.to_owned()),
+            ..SearchQuery::default()
+        };
+        Ok(state.search(query, None)?.0)
+    };
+
+    let config = SearchConfig {
+        synonyms: Some(path),
+        ..SearchConfig::default()
+    };
+
+    let potatoes = search(&SearchConfig::default(), "potato")?;
+    assert!(potatoes > 0);
+    assert_eq!(0, search(&SearchConfig::default(), "spud")?);
+    assert_eq!(potatoes, search(&config, "spud")?);
+
+    Ok(())
+}
+
+#[test]
+fn suggestions_only_correct_unknown_words() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let total_found = |fulltext: &str| -> Result<usize> {
+        let query = SearchQuery {
+            fulltext: Some(fulltext.to_owned()),
+            ..SearchQuery::default()
+        };
+        Ok(state.search(query, None)?.0)
+    };
+
+    let misspelled = "+potatoe calories:<400";
+    let found = total_found(misspelled)?;
+    assert_eq!(0, found);
+
+    let suggestion = state
+        .suggest(misspelled, None, found)
+        .expect("misspelled words get corrected");
+    assert!(suggestion.starts_with('+'));
+    assert!(suggestion.ends_with(" calories:<400"));
+    assert!(total_found(&suggestion)? > 0);
+
+    // Nothing to correct
+    assert_eq!(None, state.suggest("potato", None, 0));
+    // Found enough
+    assert_eq!(None, state.suggest(misspelled, None, 100));
+
+    Ok(())
+}
+
+#[test]
+fn suggestions_keep_the_meaning_of_match_all() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let all = state
+        .suggest("potatoe OR chicken", Some(Match::All), 0)
+        .expect("misspelled words get corrected");
+    assert!(all.ends_with(" OR chicken"), "{}", all);
+    assert!(!all.starts_with("potatoe "));
+
+    let any = state
+        .suggest("potatoe OR chicken", Some(Match::Any), 0)
+        .expect("misspelled words get corrected");
+    assert!(!any.contains(" OR "), "{}", any);
+
+    assert_eq!(
+        "bacon OR eggs",
+        state.normalize("bacon OR eggs", Some(Match::All))
+    );
+    assert_eq!(
+        "+bacon +eggs",
+        state.normalize("bacon eggs", Some(Match::All))
+    );
+    assert_eq!("bacon eggs", state.normalize("bacon OR eggs", None));
+
+    Ok(())
+}
+
+#[test]
+fn minimum_should_match_narrows_searches() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let total_found = |json: &str| -> Result<usize> {
+        let query: SearchQuery = serde_json::from_str(json).expect("valid query");
+        Ok(state.search(query, None)?.0)
+    };
+
+    let any = total_found(r#"{"fulltext": "chicken garlic lemon potato"}"#)?;
+    let half = total_found(
+        r#"{"fulltext": "chicken garlic lemon potato", "minimum_should_match": "50%"}"#,
+    )?;
+    let all =
+        total_found(r#"{"fulltext": "chicken garlic lemon potato", "minimum_should_match": 4}"#)?;
+
+    assert!(any > half, "{} > {}", any, half);
+    assert!(half > all, "{} > {}", half, all);
+    assert_eq!(
+        all,
+        total_found(r#"{"fulltext": "+chicken +garlic +lemon +potato"}"#)?
+    );
+
+    assert!(serde_json::from_str::<SearchQuery>(r#"{"minimum_should_match": "150%"}"#).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn match_all_requires_every_word() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let search = |json: &str| -> Result<(usize, Vec<RecipeId>)> {
+        let query: SearchQuery = serde_json::from_str(json).expect("valid query");
+        let (total, found_ids, _after, _agg) = state.search(query, None)?;
+        Ok((total, found_ids))
+    };
+
+    let all = search(r#"{"fulltext": "chicken garlic", "match": "all"}"#)?;
+    assert!(all.0 > 0);
+    assert_eq!(all, search(r#"{"fulltext": "+chicken +garlic"}"#)?);
+    assert_eq!(
+        search(r#"{"fulltext": "chicken garlic"}"#)?,
+        search(r#"{"fulltext": "chicken garlic", "match": "any"}"#)?
+    );
+    assert_eq!(
+        search(r#"{"fulltext": "+chicken +(garlic OR lemon)"}"#)?,
+        search(r#"{"fulltext": "chicken (garlic OR lemon)", "match": "all"}"#)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn query_limits_bound_fulltext_searches() -> Result<()> {
+    let mut config = SearchConfig::default();
+    config.query_limits.max_clauses = Some(2);
+    let state = SearchState::new(&GLOBAL.index, &config, usize::MAX)?;
+
+    let query = |json: &str| -> SearchQuery { serde_json::from_str(json).expect("valid query") };
+    let search = |json: &str| -> Result<(usize, Vec<RecipeId>)> {
+        let (total, found_ids, _after, _agg) = state.search(query(json), None)?;
+        Ok((total, found_ids))
+    };
+
+    let long = r#"{"fulltext": "chicken garlic lemon"}"#;
+    assert_eq!(search(r#"{"fulltext": "chicken garlic"}"#)?, search(long)?);
+    assert_eq!(Ok(()), state.check_limits(&query(long)));
+
+    config.reject_expensive_queries = true;
+    let state = SearchState::new(&GLOBAL.index, &config, usize::MAX)?;
+    assert_eq!(
+        Err(LimitExceeded::Clauses(2)),
+        state.check_limits(&query(long))
+    );
+    assert_eq!(
+        Ok(()),
+        state.check_limits(&query(r#"{"fulltext": "chicken garlic"}"#))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn pruned_relevance_searches_find_the_same_recipes() -> Result<()> {
+    let config = SearchConfig {
+        suggestion_threshold: None,
+        exact_total: None,
+        ..SearchConfig::default()
+    };
+    let exhaustive = SearchState::new(&GLOBAL.index, &config, 0)?;
+
+    let config = SearchConfig {
+        exact_total: Some(0),
+        ..config
+    };
+    let pruned = SearchState::new(&GLOBAL.index, &config, 0)?;
+
+    for fulltext in &["chicken", "salt pepper", "garlic \"olive oil\" lemon^2"] {
+        let query = SearchQuery {
+            fulltext: Some((*fulltext).to_owned()),
+            num_items: Some(5),
+            ..SearchQuery::default()
+        };
+
+        let mut after = None;
+        let mut pruned_after = None;
+        for _page in 0..4 {
+            let (total, found_ids, next, _agg) = exhaustive.search(query.clone(), after)?;
+            let (pruned_total, pruned_ids, pruned_next, _agg) =
+                pruned.search(query.clone(), pruned_after)?;
+
+            assert!(total >= pruned_total, "[{}] counted too many", fulltext);
+            assert_eq!(found_ids, pruned_ids, "[{}] found different ids", fulltext);
+            assert_eq!(next.is_some(), pruned_next.is_some());
+
+            after = next;
+            pruned_after = pruned_next;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn explanations_match_search_scores() -> Result<()> {
+    let state = SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?;
+
+    let query = SearchQuery {
+        fulltext: Some("chicken garlic".to_owned()),
+        num_items: Some(1),
+        ..SearchQuery::default()
+    };
+
+    let (score, recipe_id) = match state.search(query.clone(), None)?.2 {
+        Some(After::Relevance(score, recipe_id)) => (score, recipe_id),
+        _ => panic!("Relevance searches paginate by score"),
+    };
+
+    let explanation = state
+        .explain(&query, recipe_id)?
+        .expect("the top recipe is found by the search");
+    assert!((score - explanation.value()).abs() < 1e-5);
+    assert!(explanation.to_pretty_json().contains("the max"));
+
+    let missing = SearchQuery {
+        fulltext: Some("+chicken +garlic".to_owned()),
+        num_items: Some(255),
+        ..SearchQuery::default()
+    };
+    let (total, found_ids, _after, _agg) = state.search(missing.clone(), None)?;
+    assert_eq!(total, found_ids.len());
+    let not_found = GLOBAL
+        .db
+        .keys()
+        .find(|id| !found_ids.contains(id))
+        .expect("not every recipe has chicken and garlic");
+    assert!(state.explain(&missing, *not_found)?.is_none());
+
+    // Unknown recipes can't be explained either
+    assert!(state.explain(&query, RecipeId::MAX)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn indices_without_collapse_fields_still_open() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let _fields = RecipeIndex::from(&mut builder);
+    let current = builder.build();
+
+    // As built before collapsing was supported
+    let mut builder = SchemaBuilder::new();
+    for (_field, entry) in current.fields() {
+        if entry.name() != "cluster_id" && entry.name() != "crawl_host" {
+            builder.add_field(entry.clone());
+        }
+    }
+
+    let index = Index::create_in_ram(builder.build());
+    let cantine = RecipeIndex::try_from(&index.schema())?;
+    assert_eq!(None, cantine.collapse_field(Collapse::Cluster));
+    assert_eq!(None, cantine.collapse_field(Collapse::Host));
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values().take(10) {
+        writer.add_document(cantine.make_document(recipe));
+    }
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let (total, _found_ids, _after) =
+        cantine.search(&searcher, &AllQuery, 10, Sort::Relevance, None)?;
+    assert_eq!(10, total);
+
+    assert!(cantine
+        .search_collapsed(
+            &searcher,
+            &AllQuery,
+            10,
+            Sort::Relevance,
+            None,
+            Collapse::Host
+        )
+        .is_err());
+
+    let state = SearchState::new(&index, &SearchConfig::default(), usize::MAX)?;
+    assert!(!state.can_collapse(Collapse::Cluster));
+    assert!(
+        SearchState::new(&GLOBAL.index, &SearchConfig::default(), usize::MAX)?
+            .can_collapse(Collapse::Cluster)
+    );
+
+    Ok(())
+}
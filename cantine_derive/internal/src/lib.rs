@@ -150,6 +150,14 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
     });
 
+    let named_fields = fields.iter().map(|field| {
+        let name = field.ident;
+        let quoted = format!("{}", name);
+        quote_spanned! { field.span()=>
+            (#quoted, self.#name)
+        }
+    });
+
     let interpret_code = fields.iter().map(|field| {
         let name = field.ident;
 
@@ -296,6 +304,10 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                 #(#add_to_doc_code);*
             }
 
+            pub fn fields(&self) -> Vec<(&'static str, tantivy::schema::Field)> {
+                vec![#(#named_fields),*]
+            }
+
             pub fn with_flags<O: Into<tantivy::schema::IntOptions>>(
                 builder: &mut tantivy::schema::SchemaBuilder,
                 flags: O
@@ -59,6 +59,22 @@ fn filter_query_interpretation() {
     );
 }
 
+#[test]
+fn fields_are_listed_by_name() {
+    let mut builder = SchemaBuilder::new();
+    let fields = Feat::create_schema(&mut builder, INDEXED);
+
+    assert_eq!(
+        vec![
+            ("a", fields.a),
+            ("b", fields.b),
+            ("c", fields.c),
+            ("d", fields.d)
+        ],
+        fields.fields()
+    );
+}
+
 #[test]
 fn add_to_doc_sets_fields_properly() {
     let mut builder = SchemaBuilder::new();
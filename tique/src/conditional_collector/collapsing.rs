@@ -0,0 +1,554 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, HashMap},
+    marker::PhantomData,
+};
+
+use tantivy::{
+    collector::{Collector, CustomScorer, CustomSegmentScorer, SegmentCollector},
+    DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+use super::{
+    topk::{TopK, TopKProvider},
+    traits::{CheckCondition, ConditionForSegment, GroupForSegment, SegmentGroup},
+    CollectionResult,
+};
+
+/// A TopCollector that only keeps the top document of each group.
+///
+/// Every matching document is assigned to a group via the given
+/// `GroupForSegment` implementation (a u64 FAST `Field` works out
+/// of the box) and only the best document of each group, following
+/// the chosen ordering, is considered for the top results.
+///
+/// Conditions are checked against the top document of each group
+/// instead of against every matching document, so paginating with
+/// a `(T, DocAddress)` cursor never yields a group that appeared
+/// in a previous page.
+///
+/// # Examples
+///
+/// ## Collapsing by a fast field
+///
+/// ```no_run
+/// # use tique::conditional_collector::{CollapsingTopCollector, Descending};
+/// # let site_field = tantivy::schema::Field::from_field_id(0);
+/// # let limit = 10;
+/// # let condition = true;
+/// let collector =
+///     CollapsingTopCollector::<tantivy::Score, Descending, _, _>::new(limit, condition, site_field);
+/// ```
+///
+/// ## Sorting by a fast field
+///
+/// ```no_run
+/// # use tique::conditional_collector::{Ascending, CollapsingTopCollector};
+/// # let rank_field = tantivy::schema::Field::from_field_id(0);
+/// # let site_field = tantivy::schema::Field::from_field_id(1);
+/// # let limit = 10;
+/// # let condition = true;
+/// let collector =
+///     CollapsingTopCollector::<f64, Ascending, _, _>::new(limit, condition, site_field)
+///         .top_fast_field(rank_field);
+/// ```
+pub struct CollapsingTopCollector<T, P, CF, GF> {
+    limit: usize,
+    condition_for_segment: CF,
+    group_for_segment: GF,
+    _score: PhantomData<T>,
+    _provider: PhantomData<P>,
+}
+
+impl<T, P, CF, GF> CollapsingTopCollector<T, P, CF, GF>
+where
+    T: PartialOrd,
+    P: TopKProvider<T, DocId>,
+    CF: ConditionForSegment<T>,
+    GF: GroupForSegment,
+{
+    /// Creates a new CollapsingTopCollector with capacity of `limit`
+    /// groups, respecting the given `ConditionForSegment` and
+    /// grouping documents via the `GroupForSegment` implementation.
+    pub fn new(limit: usize, condition_for_segment: CF, group_for_segment: GF) -> Self {
+        if limit < 1 {
+            panic!("Limit must be greater than 0");
+        }
+        CollapsingTopCollector {
+            limit,
+            condition_for_segment,
+            group_for_segment,
+            _score: PhantomData,
+            _provider: PhantomData,
+        }
+    }
+
+    fn segment_collector(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> CollapsingSegmentCollector<T, CF::Type, GF::Type> {
+        CollapsingSegmentCollector::new(
+            segment_id,
+            P::Child::ASCENDING,
+            self.condition_for_segment.for_segment(reader),
+            self.group_for_segment.for_segment(reader),
+        )
+    }
+}
+
+impl<T, P, CF, GF> CollapsingTopCollector<T, P, CF, GF>
+where
+    T: 'static + Copy + Send + Sync + PartialOrd,
+    P: 'static + Send + Sync + TopKProvider<T, DocId>,
+    CF: Send + Sync + ConditionForSegment<T>,
+    GF: Send + Sync + GroupForSegment,
+{
+    /// Transforms this collector into that that uses the given
+    /// scorer instead of the default scoring functionality.
+    pub fn with_custom_scorer<C: CustomScorer<T>>(
+        self,
+        custom_scorer: C,
+    ) -> impl Collector<Fruit = CollapsedResult<T>> {
+        CustomScoreCollapsingCollector {
+            collector: self,
+            scorer_for_segment: custom_scorer,
+        }
+    }
+}
+
+macro_rules! impl_top_fast_field {
+    ($type: ident, $err: literal) => {
+        impl<P, CF, GF> CollapsingTopCollector<$type, P, CF, GF>
+        where
+            P: 'static + Send + Sync + TopKProvider<$type, DocId>,
+            CF: Send + Sync + ConditionForSegment<$type>,
+            GF: Send + Sync + GroupForSegment,
+        {
+            /// Transforms this collector into one that sorts by the given
+            /// fast field. Will panic if the field is not FAST or the wrong
+            /// type.
+            pub fn top_fast_field(
+                self,
+                field: tantivy::schema::Field,
+            ) -> impl Collector<Fruit = CollapsedResult<$type>> {
+                let scorer_for_segment = move |reader: &SegmentReader| {
+                    let ff = reader.fast_fields().$type(field).expect($err);
+                    move |doc_id| ff.get(doc_id)
+                };
+                self.with_custom_scorer(scorer_for_segment)
+            }
+        }
+    };
+}
+
+impl_top_fast_field!(u64, "Field is not a fast u64 field");
+impl_top_fast_field!(i64, "Field is not a fast i64 field");
+impl_top_fast_field!(f64, "Field is not a fast f64 field");
+
+impl<P, CF, GF> Collector for CollapsingTopCollector<Score, P, CF, GF>
+where
+    P: 'static + Send + Sync + TopKProvider<Score, DocId>,
+    CF: Sync + ConditionForSegment<Score>,
+    GF: Sync + GroupForSegment,
+{
+    type Fruit = CollapsedResult<Score>;
+    type Child = CollapsingSegmentCollector<Score, CF::Type, GF::Type>;
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        Ok(CollapsedResult::merge_many::<P>(self.limit, children))
+    }
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(self.segment_collector(segment_id, reader))
+    }
+}
+
+struct CustomScoreCollapsingCollector<T, P, CF, GF, S> {
+    collector: CollapsingTopCollector<T, P, CF, GF>,
+    scorer_for_segment: S,
+}
+
+impl<T, P, CF, GF, S> Collector for CustomScoreCollapsingCollector<T, P, CF, GF, S>
+where
+    T: 'static + PartialOrd + Copy + Send + Sync,
+    P: 'static + Send + Sync + TopKProvider<T, DocId>,
+    CF: Sync + ConditionForSegment<T>,
+    GF: Sync + GroupForSegment,
+    S: CustomScorer<T>,
+{
+    type Fruit = CollapsedResult<T>;
+    type Child = CustomScoreCollapsingSegmentCollector<T, CF::Type, GF::Type, S::Child>;
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        Ok(CollapsedResult::merge_many::<P>(
+            self.collector.limit,
+            children,
+        ))
+    }
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(CustomScoreCollapsingSegmentCollector {
+            scorer: self.scorer_for_segment.segment_scorer(reader)?,
+            collector: self.collector.segment_collector(segment_id, reader),
+        })
+    }
+}
+
+pub struct CollapsingSegmentCollector<T, C, G> {
+    total: usize,
+    segment_id: SegmentLocalId,
+    ascending: bool,
+    condition: C,
+    group: G,
+    best: HashMap<u64, (T, DocId)>,
+}
+
+impl<T, C, G> CollapsingSegmentCollector<T, C, G> {
+    pub fn new(segment_id: SegmentLocalId, ascending: bool, condition: C, group: G) -> Self {
+        Self {
+            total: 0,
+            segment_id,
+            ascending,
+            condition,
+            group,
+            best: HashMap::new(),
+        }
+    }
+}
+
+impl<T, C, G> CollapsingSegmentCollector<T, C, G>
+where
+    T: Copy + PartialOrd,
+    C: CheckCondition<T>,
+    G: SegmentGroup,
+{
+    pub fn collect(&mut self, doc: DocId, score: T) {
+        self.total += 1;
+
+        let ascending = self.ascending;
+        match self.best.entry(self.group.group(doc)) {
+            Entry::Vacant(entry) => {
+                entry.insert((score, doc));
+            }
+            Entry::Occupied(mut entry) => {
+                if is_better(ascending, (score, doc), *entry.get()) {
+                    entry.insert((score, doc));
+                }
+            }
+        }
+    }
+
+    pub fn into_collapsed_result(self) -> CollapsedResult<T> {
+        let segment_id = self.segment_id;
+        let ascending = self.ascending;
+        let condition = self.condition;
+
+        let groups = self
+            .best
+            .into_iter()
+            .map(|(key, (score, doc))| Group {
+                key,
+                score,
+                doc: DocAddress(segment_id, doc),
+                passes: condition.check(segment_id, doc, score, ascending),
+            })
+            .collect();
+
+        CollapsedResult {
+            total: self.total,
+            visited: 0,
+            items: Vec::new(),
+            groups,
+        }
+    }
+}
+
+impl<C, G> SegmentCollector for CollapsingSegmentCollector<Score, C, G>
+where
+    C: CheckCondition<Score>,
+    G: SegmentGroup,
+{
+    type Fruit = CollapsedResult<Score>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        CollapsingSegmentCollector::collect(self, doc, score)
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.into_collapsed_result()
+    }
+}
+
+pub struct CustomScoreCollapsingSegmentCollector<T, C, G, S> {
+    scorer: S,
+    collector: CollapsingSegmentCollector<T, C, G>,
+}
+
+impl<T, C, G, S> SegmentCollector for CustomScoreCollapsingSegmentCollector<T, C, G, S>
+where
+    T: 'static + PartialOrd + Copy + Send + Sync,
+    C: CheckCondition<T>,
+    G: SegmentGroup,
+    S: CustomSegmentScorer<T>,
+{
+    type Fruit = CollapsedResult<T>;
+
+    fn collect(&mut self, doc: DocId, _: Score) {
+        let score = self.scorer.score(doc);
+        self.collector.collect(doc, score);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.into_collapsed_result()
+    }
+}
+
+/// The result of a `CollapsingTopCollector`. Convertible into a
+/// `CollectionResult`.
+#[derive(Debug)]
+pub struct CollapsedResult<T> {
+    /// How many documents were seen. Analogous to the result of a
+    /// simple count collector.
+    pub total: usize,
+    /// How many groups had their top document pass our condition
+    pub visited: usize,
+    /// The top document of the top found groups
+    pub items: Vec<(T, DocAddress)>,
+    /// The best document of every group seen in a segment.
+    /// Only used before merging
+    groups: Vec<Group<T>>,
+}
+
+#[derive(Debug)]
+struct Group<T> {
+    key: u64,
+    score: T,
+    doc: DocAddress,
+    passes: bool,
+}
+
+impl<T: PartialOrd + Copy> CollapsedResult<T> {
+    /// Wether the same query that created this result would have
+    /// more results if we paginated (or increased the top-k limit)
+    pub fn has_next(&self) -> bool {
+        self.visited - self.items.len() > 0
+    }
+
+    fn merge_many<P: TopKProvider<T, DocId>>(limit: usize, results: Vec<Self>) -> Self {
+        let ascending = P::Child::ASCENDING;
+
+        let mut total = 0;
+        let mut best: HashMap<u64, Group<T>> = HashMap::new();
+        for result in results {
+            total += result.total;
+
+            for group in result.groups {
+                match best.entry(group.key) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(group);
+                    }
+                    Entry::Occupied(mut entry) => {
+                        let current = entry.get();
+                        if is_better(
+                            ascending,
+                            (group.score, group.doc),
+                            (current.score, current.doc),
+                        ) {
+                            entry.insert(group);
+                        }
+                    }
+                }
+            }
+        }
+
+        let items: Vec<_> = best
+            .into_iter()
+            .filter(|(_key, group)| group.passes)
+            .map(|(_key, group)| (group.score, group.doc))
+            .collect();
+        let visited = items.len();
+
+        let merged = P::merge_many(
+            limit,
+            vec![CollectionResult {
+                total,
+                visited,
+                items,
+            }],
+        );
+
+        CollapsedResult {
+            total,
+            visited,
+            items: merged.items,
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl<T> From<CollapsedResult<T>> for CollectionResult<T> {
+    fn from(src: CollapsedResult<T>) -> Self {
+        CollectionResult {
+            total: src.total,
+            visited: src.visited,
+            items: src.items,
+        }
+    }
+}
+
+/// Wether `candidate` should replace `current` as the top of a group.
+/// Ties are broken by the lowest doc, just like the top-k does
+fn is_better<T: PartialOrd, D: Ord>(ascending: bool, candidate: (T, D), current: (T, D)) -> bool {
+    match candidate.0.partial_cmp(&current.0) {
+        Some(Ordering::Greater) => !ascending,
+        Some(Ordering::Less) => ascending,
+        _ => candidate.1 < current.1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::conditional_collector::{Ascending, Descending};
+
+    use std::collections::HashSet;
+    use tantivy::{
+        query::AllQuery,
+        schema::{self, SchemaBuilder},
+        Document, Index,
+    };
+
+    #[test]
+    fn keeps_the_best_of_each_group() {
+        let group = |doc_id: DocId| u64::from(doc_id % 3);
+
+        let mut desc = CollapsingSegmentCollector::new(0, false, true, group);
+        let mut asc = CollapsingSegmentCollector::new(0, true, true, group);
+
+        for &(doc, score) in &[(0, 0.5), (1, 0.1), (2, 0.3), (3, 0.9), (4, 0.1), (5, 0.2)] {
+            desc.collect(doc, score);
+            asc.collect(doc, score);
+        }
+
+        let mut desc_groups = desc
+            .into_collapsed_result()
+            .groups
+            .into_iter()
+            .map(|g| (g.key, g.doc.1))
+            .collect::<Vec<_>>();
+        desc_groups.sort();
+        assert_eq!(vec![(0, 3), (1, 1), (2, 2)], desc_groups);
+
+        let mut asc_groups = asc
+            .into_collapsed_result()
+            .groups
+            .into_iter()
+            .map(|g| (g.key, g.doc.1))
+            .collect::<Vec<_>>();
+        asc_groups.sort();
+        // Doc 1 and 4 score the same: lowest doc wins
+        assert_eq!(vec![(0, 0), (1, 1), (2, 5)], asc_groups);
+    }
+
+    #[test]
+    fn condition_is_checked_on_the_best_of_each_group() {
+        // Only even docs pass, but group 0's best is odd
+        let condition = |_sid, doc: DocId, _score, _asc| doc % 2 == 0;
+        let mut collector = CollapsingSegmentCollector::new(0, false, condition, |_| 0);
+
+        collector.collect(0, 0.1);
+        collector.collect(1, 0.9);
+
+        let result =
+            CollapsedResult::merge_many::<Descending>(10, vec![collector.into_collapsed_result()]);
+
+        assert_eq!(2, result.total);
+        assert_eq!(0, result.visited);
+        assert!(result.items.is_empty());
+    }
+
+    #[test]
+    fn pagination_yields_every_group_once() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let group_field = builder.add_u64_field("group", schema::FAST);
+        let rank_field = builder.add_f64_field("rank", schema::FAST);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        const NUM_GROUPS: u64 = 17;
+        let mut add_docs = |from: u64, to: u64| -> Result<()> {
+            for i in from..to {
+                let mut doc = Document::new();
+                doc.add_u64(group_field, i % NUM_GROUPS);
+                // Unique ranks: 37 and 100 are coprime
+                doc.add_f64(rank_field, ((i * 37) % 100) as f64);
+                writer.add_document(doc);
+            }
+            // Groups span multiple segments
+            writer.commit()?;
+            Ok(())
+        };
+
+        add_docs(0, 40)?;
+        add_docs(40, 100)?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let mut seen = HashSet::new();
+        let mut after = None;
+        loop {
+            let result = if let Some(after) = after {
+                let collector =
+                    CollapsingTopCollector::<f64, Ascending, _, _>::new(3, after, group_field)
+                        .top_fast_field(rank_field);
+                searcher.search(&AllQuery, &collector)?
+            } else {
+                let collector =
+                    CollapsingTopCollector::<f64, Ascending, _, _>::new(3, true, group_field)
+                        .top_fast_field(rank_field);
+                searcher.search(&AllQuery, &collector)?
+            };
+
+            assert_eq!(100, result.total);
+
+            for (_rank, addr) in result.items.iter() {
+                let group = searcher
+                    .segment_reader(addr.0)
+                    .fast_fields()
+                    .u64(group_field)
+                    .unwrap()
+                    .get(addr.1);
+                assert!(seen.insert(group), "Group {} seen twice", group);
+            }
+
+            if !result.has_next() {
+                break;
+            }
+            after = result.items.last().copied();
+        }
+
+        assert_eq!(NUM_GROUPS as usize, seen.len());
+
+        Ok(())
+    }
+}
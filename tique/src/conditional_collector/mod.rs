@@ -96,11 +96,33 @@
 //! going without ever having to increase `limit`.
 //!
 //! Check `examples/conditional_collector_tutorial.rs` for more details.
+//!
+//! # Collapsing
+//!
+//! When many results are essentially the same (say, copies of a page
+//! under different urls) you can use the `CollapsingTopCollector` to
+//! assign documents to groups and only collect the top document of
+//! each group. Pagination works just the same:
+//!
+//! ```no_run
+//! # use tantivy::DocAddress;
+//! # use tique::conditional_collector::{CollapsingTopCollector,Descending};
+//! # let site_field = tantivy::schema::Field::from_field_id(0);
+//! let limit = 10;
+//! let condition_for_segment = (0.42, DocAddress(0, 1));
+//! let collector = CollapsingTopCollector::<_, Descending, _, _>::new(
+//!     limit,
+//!     condition_for_segment,
+//!     site_field,
+//! );
+//! ```
+mod collapsing;
 mod custom_score;
 mod top_collector;
 pub(crate) mod topk;
 mod traits;
 
+pub use collapsing::{CollapsedResult, CollapsingTopCollector};
 pub use top_collector::{CollectionResult, TopCollector};
 pub use topk::{Ascending, Descending};
 pub use traits::*;
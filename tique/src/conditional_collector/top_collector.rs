@@ -2,9 +2,12 @@ use std::marker::PhantomData;
 
 use tantivy::{
     collector::{Collector, CustomScorer, SegmentCollector},
-    DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader,
+    query::{Query, Scorer},
+    DocAddress, DocId, DocSet, Result, Score, Searcher, SegmentLocalId, SegmentReader,
 };
 
+use crate::dismax::pruning_weight;
+
 use super::{
     custom_score::CustomScoreTopCollector,
     topk::{TopK, TopKProvider},
@@ -193,6 +196,67 @@ where
     }
 }
 
+impl<P, CF> TopCollector<Score, P, CF>
+where
+    P: 'static + Send + Sync + TopKProvider<Score, DocId>,
+    CF: Sync + ConditionForSegment<Score>,
+{
+    /// Collects the top documents matching `query`, like searching
+    /// with this collector does, but skipping the documents that can't
+    /// score high enough to make it to the top
+    ///
+    /// Only queries in `Descending` order are pruned, and only when
+    /// `query` is a `DisMaxQuery` or a `BooleanQuery` with nothing but
+    /// optional clauses (see `DisMaxQuery` for what gets skipped).
+    /// Anything else is searched as usual.
+    ///
+    /// Skipping only starts after collecting more than `exact_total`
+    /// documents, so the `total` and `visited` counts of the result
+    /// are exact up to that and just a lower bound past it.
+    pub fn search_pruned(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        exact_total: usize,
+    ) -> Result<CollectionResult<Score>> {
+        let weight = match pruning_weight(query, searcher)? {
+            Some(weight) if !<P::Child as TopK<Score, DocId>>::ASCENDING => weight,
+            _ => return searcher.search(query, self),
+        };
+
+        let mut total = 0;
+        let mut fruits = Vec::with_capacity(searcher.segment_readers().len());
+
+        for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+            let mut collector = self.for_segment(segment_ord as SegmentLocalId, reader)?;
+            let mut scorer = weight.pruning_scorer(reader)?;
+            let delete_bitset = reader.delete_bitset();
+
+            while scorer.advance() {
+                let doc = scorer.doc();
+                if delete_bitset.map_or(false, |deleted| deleted.is_deleted(doc)) {
+                    continue;
+                }
+
+                collector.collect(doc, scorer.score());
+                total += 1;
+
+                // Having visited more than `limit` documents keeps
+                // `has_next()` true no matter what gets skipped
+                if total > exact_total && collector.visited > self.limit {
+                    if let Some(threshold) = collector.topk.threshold() {
+                        scorer.set_threshold(*threshold);
+                    }
+                }
+            }
+
+            fruits.push(collector.into_unsorted_collection_result());
+        }
+
+        self.merge_fruits(fruits)
+    }
+}
+
 pub struct TopSegmentCollector<T, K, C> {
     total: usize,
     visited: usize,
@@ -323,8 +387,10 @@ mod tests {
         Ascending, Descending,
     };
 
+    use crate::{dismax::BoostedQuery, DisMaxQuery, MinimumShouldMatch};
+
     use tantivy::{
-        query::{AllQuery, TermQuery},
+        query::{AllQuery, BooleanQuery, Occur, TermQuery},
         schema, Document, Index, Result, Term,
     };
 
@@ -500,6 +566,128 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn pruned_search_finds_the_same_top() -> Result<()> {
+        let mut builder = schema::SchemaBuilder::new();
+        let title = builder.add_text_field("title", schema::TEXT);
+        let body = builder.add_text_field("body", schema::TEXT);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        const WORDS: [&str; 6] = ["bacon", "cheese", "eggs", "toast", "jam", "tea"];
+        let mut seed = 42u32;
+        let mut text = |len: u32| {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    // Makes the first words more frequent
+                    let idx = ((seed >> 16) % 36) as f32;
+                    WORDS[idx.sqrt() as usize]
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        for i in 0..600 {
+            let mut doc = Document::new();
+            doc.add_text(title, &text(1 + i % 3));
+            doc.add_text(body, &text(3 + i % 7));
+            writer.add_document(doc);
+
+            // More than one segment
+            if i == 300 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let term = |field, word| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, word),
+                schema::IndexRecordOption::WithFreqs,
+            ))
+        };
+        let per_field = |word| -> Box<dyn Query> {
+            Box::new(DisMaxQuery::new(
+                vec![
+                    Box::new(BoostedQuery::new(term(title, word), 1.5)),
+                    term(body, word),
+                ],
+                0.1,
+            ))
+        };
+
+        let mut strict = DisMaxQuery::new(
+            vec![per_field("bacon"), per_field("jam"), per_field("tea")],
+            1.0,
+        );
+        strict.set_minimum_should_match(Some(MinimumShouldMatch::Absolute(2)));
+
+        let queries: Vec<Box<dyn Query>> = vec![
+            per_field("bacon"),
+            per_field("tea"),
+            Box::new(DisMaxQuery::new(
+                vec![per_field("bacon"), per_field("cheese"), per_field("tea")],
+                1.0,
+            )),
+            Box::new(strict),
+        ];
+
+        for query in &queries {
+            for &limit in &[1, 5, 50] {
+                let collector = TopCollector::<_, Descending, _>::new(limit, true);
+                let expected = searcher.search(query, &collector)?;
+                let pruned = collector.search_pruned(&searcher, query.as_ref(), 0)?;
+
+                assert_eq!(expected.items, pruned.items);
+                assert_eq!(expected.has_next(), pruned.has_next());
+                assert!(pruned.total <= expected.total);
+
+                // Pagination conditions still apply
+                let (score, addr) = expected.items[expected.items.len() / 2];
+                let collector = TopCollector::<_, Descending, _>::new(limit, (score, addr));
+                assert_eq!(
+                    searcher.search(query, &collector)?.items,
+                    collector.search_pruned(&searcher, query.as_ref(), 0)?.items
+                );
+
+                let exact = collector.search_pruned(&searcher, query.as_ref(), usize::MAX)?;
+                assert_eq!(searcher.search(query, &collector)?.total, exact.total);
+            }
+        }
+
+        let common = per_field("bacon");
+        let collector = TopCollector::<_, Descending, _>::new(1, true);
+        assert!(
+            collector
+                .search_pruned(&searcher, common.as_ref(), 0)?
+                .total
+                < searcher.search(&common, &collector)?.total,
+            "Should've skipped some documents"
+        );
+
+        // Optional boolean clauses are pruned too, summing scores in
+        // a different order
+        let boolean = BooleanQuery::from(vec![
+            (Occur::Should, term(body, "cheese")),
+            (Occur::Should, term(body, "jam")),
+        ]);
+        let collector = TopCollector::<_, Descending, _>::new(10, true);
+        let expected = searcher.search(&boolean, &collector)?;
+        let pruned = collector.search_pruned(&searcher, &boolean, 0)?;
+        assert_eq!(expected.items.len(), pruned.items.len());
+        for ((expected, _), (score, _)) in expected.items.iter().zip(pruned.items.iter()) {
+            assert!((expected - score).abs() < 1e-5);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn fast_field_collection() -> Result<()> {
         let mut builder = schema::SchemaBuilder::new();
@@ -8,6 +8,8 @@ use super::CollectionResult;
 pub trait TopK<T, D> {
     const ASCENDING: bool;
     fn visit(&mut self, doc: D, score: T);
+    /// The worst score kept, once there's no room left
+    fn threshold(&self) -> Option<&T>;
     fn into_sorted_vec(self) -> Vec<(D, T)>;
     fn into_vec(self) -> Vec<(D, T)>;
 }
@@ -146,6 +148,14 @@ impl<T: PartialOrd, D: Ord> TopK<T, D> for AscendingTopK<T, D> {
         AscendingTopK::visit(self, doc, score);
     }
 
+    fn threshold(&self) -> Option<&T> {
+        if self.heap.len() < self.limit {
+            None
+        } else {
+            self.heap.peek().map(|head| &head.score)
+        }
+    }
+
     fn into_sorted_vec(self) -> Vec<(D, T)> {
         AscendingTopK::into_sorted_vec(self)
     }
@@ -162,6 +172,14 @@ impl<T: PartialOrd, D: Ord> TopK<T, D> for DescendingTopK<T, D> {
         DescendingTopK::visit(self, doc, score);
     }
 
+    fn threshold(&self) -> Option<&T> {
+        if self.heap.len() < self.limit {
+            None
+        } else {
+            self.heap.peek().map(|head| &head.0.score)
+        }
+    }
+
     fn into_sorted_vec(self) -> Vec<(D, T)> {
         DescendingTopK::into_sorted_vec(self)
     }
@@ -1,6 +1,8 @@
 use std::cmp::Ordering;
 
-use tantivy::{DocAddress, DocId, SegmentLocalId, SegmentReader};
+use tantivy::{
+    fastfield::FastFieldReader, schema::Field, DocAddress, DocId, SegmentLocalId, SegmentReader,
+};
 
 use super::topk::Scored;
 
@@ -86,3 +88,61 @@ where
             == wanted
     }
 }
+
+/// A trait that allows assigning documents to arbitrary groups so
+/// that only the top document of each group is collected.
+///
+/// Group keys are compared across segments, so documents from
+/// different segments with the same key belong to the same group.
+pub trait GroupForSegment: Clone {
+    /// The concrete type of the result from calling `for_segment`
+    type Type: SegmentGroup;
+
+    /// Creates a `Self::Type` instance responsible for telling the
+    /// group of the matching documents in the given segment reader.
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Type;
+}
+
+impl<G, F> GroupForSegment for F
+where
+    F: Clone + Fn(&SegmentReader) -> G,
+    G: SegmentGroup,
+{
+    type Type = G;
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Type {
+        (self)(reader)
+    }
+}
+
+/// Groups documents by the value of a u64 fast field. Will panic if
+/// the field is not FAST or the wrong type.
+impl GroupForSegment for Field {
+    type Type = FastFieldReader<u64>;
+    fn for_segment(&self, reader: &SegmentReader) -> Self::Type {
+        reader
+            .fast_fields()
+            .u64(*self)
+            .expect("Field is not a fast u64 field")
+    }
+}
+
+/// Tells which group a document belongs to.
+pub trait SegmentGroup: 'static {
+    /// The key identifying the group of the given document
+    fn group(&self, doc_id: DocId) -> u64;
+}
+
+impl<F> SegmentGroup for F
+where
+    F: 'static + Fn(DocId) -> u64,
+{
+    fn group(&self, doc_id: DocId) -> u64 {
+        (self)(doc_id)
+    }
+}
+
+impl SegmentGroup for FastFieldReader<u64> {
+    fn group(&self, doc_id: DocId) -> u64 {
+        self.get(doc_id)
+    }
+}
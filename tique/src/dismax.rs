@@ -1,9 +1,16 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
 use tantivy::{
     self,
-    query::{EmptyScorer, Explanation, Query, Scorer, Weight},
-    DocId, DocSet, Result, Score, Searcher, SegmentReader, SkipResult, TantivyError,
+    query::{
+        BooleanQuery, BoostQuery, EmptyScorer, Explanation, Occur, Query, Scorer, TermQuery, Weight,
+    },
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, SkipResult, TantivyError, Term,
 };
 
+// Same as tantivy's BM25, which is what bounds the score of a term
+const K1: f32 = 1.2;
+
 /// A Maximum Disjunction query, as popularized by Lucene/Solr
 ///
 /// A DisMax query is one that behaves as the union of its sub-queries and
@@ -15,10 +22,112 @@ use tantivy::{
 /// turn it up to `1.0` the score ends up being the sum of all scores, just
 /// like a plain "should" BooleanQuery would.
 ///
+/// By default a document matches if any of the sub-queries matches it,
+/// which can be made stricter via `set_minimum_should_match`.
+///
+/// When collecting the top documents via `TopCollector::search_pruned`,
+/// documents that can't score high enough to make it to the top are
+/// skipped, as long as the sub-queries are term queries (or boosted,
+/// boolean or dismax combinations of them).
+///
 #[derive(Debug)]
 pub struct DisMaxQuery {
     disjuncts: Vec<Box<dyn Query>>,
     tiebreaker: f32,
+    minimum_should_match: Option<MinimumShouldMatch>,
+}
+
+/// How many of a set of optional clauses must match a document
+///
+/// Reads from and renders to text like `3` or `75%`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinimumShouldMatch {
+    /// At least this many clauses
+    Absolute(usize),
+    /// At least this percentage (up to 100) of the clauses, rounded down
+    Percentage(u8),
+}
+
+impl MinimumShouldMatch {
+    /// How many out of `num_clauses` clauses must match
+    ///
+    /// Never more than `num_clauses`, so asking for 3 clauses out of
+    /// 2 requires both and never less than 1, since a document that
+    /// matches no clause at all isn't a match.
+    pub fn resolve(&self, num_clauses: usize) -> usize {
+        let minimum = match self {
+            MinimumShouldMatch::Absolute(minimum) => *minimum,
+            MinimumShouldMatch::Percentage(percentage) => {
+                num_clauses * usize::from(*percentage) / 100
+            }
+        };
+
+        minimum.min(num_clauses).max(1)
+    }
+}
+
+impl fmt::Display for MinimumShouldMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinimumShouldMatch::Absolute(minimum) => write!(f, "{}", minimum),
+            MinimumShouldMatch::Percentage(percentage) => write!(f, "{}%", percentage),
+        }
+    }
+}
+
+impl FromStr for MinimumShouldMatch {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid minimum_should_match: {}", input);
+
+        if let Some(percentage) = input.strip_suffix('%') {
+            match percentage.parse::<u8>() {
+                Ok(percentage) if percentage <= 100 => {
+                    Ok(MinimumShouldMatch::Percentage(percentage))
+                }
+                _ => Err(invalid()),
+            }
+        } else {
+            input
+                .parse()
+                .map(MinimumShouldMatch::Absolute)
+                .map_err(|_| invalid())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for MinimumShouldMatch {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            MinimumShouldMatch::Absolute(minimum) => serializer.serialize_u64(*minimum as u64),
+            MinimumShouldMatch::Percentage(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MinimumShouldMatch {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        // Either a number or text like `75%`
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(usize),
+            Text(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(minimum) => Ok(MinimumShouldMatch::Absolute(minimum)),
+            Repr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
 }
 
 impl DisMaxQuery {
@@ -30,8 +139,16 @@ impl DisMaxQuery {
         Self {
             disjuncts,
             tiebreaker,
+            minimum_should_match: None,
         }
     }
+
+    /// Only match documents that at least this many sub-queries match
+    ///
+    /// `None`, the default, is equivalent to requiring a single one.
+    pub fn set_minimum_should_match(&mut self, minimum: Option<MinimumShouldMatch>) {
+        self.minimum_should_match = minimum;
+    }
 }
 
 impl Clone for DisMaxQuery {
@@ -39,34 +156,230 @@ impl Clone for DisMaxQuery {
         Self {
             disjuncts: self.disjuncts.iter().map(|q| q.box_clone()).collect(),
             tiebreaker: self.tiebreaker,
+            minimum_should_match: self.minimum_should_match,
         }
     }
 }
 
+impl DisMaxQuery {
+    fn minimum_match(&self) -> usize {
+        self.minimum_should_match
+            .map_or(1, |minimum| minimum.resolve(self.disjuncts.len()))
+    }
+}
+
 impl Query for DisMaxQuery {
     fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        let weights = self
+            .disjuncts
+            .iter()
+            .map(|d| d.weight(searcher, scoring_enabled))
+            .collect::<Result<Vec<_>>>()?;
+        // Bounds are only needed when pruning
+        let bounds = vec![Score::INFINITY; weights.len()];
+
         Ok(Box::new(DisMaxWeight::new(
-            self.disjuncts
-                .iter()
-                .map(|d| d.weight(searcher, scoring_enabled))
-                .collect::<Result<Vec<_>>>()?,
+            weights,
+            bounds,
             self.tiebreaker,
+            self.minimum_match(),
         )))
     }
 }
 
-struct DisMaxWeight {
+/// Same as tantivy's `BoostQuery`, but transparent to the score
+/// bounds used for pruning
+#[derive(Debug)]
+pub(crate) struct BoostedQuery {
+    query: Box<dyn Query>,
+    boost: f32,
+}
+
+impl BoostedQuery {
+    pub fn new(query: Box<dyn Query>, boost: f32) -> Self {
+        Self { query, boost }
+    }
+}
+
+impl Clone for BoostedQuery {
+    fn clone(&self) -> Self {
+        Self {
+            query: self.query.box_clone(),
+            boost: self.boost,
+        }
+    }
+}
+
+impl Query for BoostedQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        BoostQuery::new(self.query.box_clone(), self.boost).weight(searcher, scoring_enabled)
+    }
+
+    fn query_terms(&self, term_set: &mut BTreeSet<Term>) {
+        self.query.query_terms(term_set)
+    }
+}
+
+/// An upper bound for the score of every document `query` matches,
+/// when it can be known without looking at the documents
+fn max_score(query: &dyn Query, searcher: &Searcher) -> Option<Score> {
+    let query = query.as_any();
+
+    if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+        Some(term_max_score(term_query.term(), searcher))
+    } else if let Some(boosted) = query.downcast_ref::<BoostedQuery>() {
+        max_score(boosted.query.as_ref(), searcher).map(|bound| bound * boosted.boost)
+    } else if let Some(dismax) = query.downcast_ref::<DisMaxQuery>() {
+        let bounds = dismax
+            .disjuncts
+            .iter()
+            .map(|disjunct| max_score(disjunct.as_ref(), searcher))
+            .collect::<Option<Vec<_>>>()?;
+        Some(combine_bounds(bounds, dismax.tiebreaker))
+    } else if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+        // Prohibited clauses don't add to the score and the others
+        // are summed
+        boolean
+            .clauses()
+            .iter()
+            .filter(|(occur, _query)| *occur != Occur::MustNot)
+            .map(|(_occur, query)| max_score(query.as_ref(), searcher))
+            .sum()
+    } else {
+        None
+    }
+}
+
+/// BM25 saturates: no matter how frequent, a term never scores more
+/// than its idf times `1 + K1`
+fn term_max_score(term: &Term, searcher: &Searcher) -> Score {
+    let doc_count = searcher
+        .segment_readers()
+        .iter()
+        .map(|reader| u64::from(reader.max_doc()))
+        .sum::<u64>();
+    let doc_freq = searcher.doc_freq(term);
+
+    let x = ((doc_count - doc_freq) as f32 + 0.5) / (doc_freq as f32 + 0.5);
+    (1.0 + x).ln() * (1.0 + K1)
+}
+
+/// The highest score a document matching every scorer with these
+/// bounds could get
+fn combine_bounds<I: IntoIterator<Item = Score>>(bounds: I, tiebreaker: f32) -> Score {
+    let mut max = 0.0;
+    let mut sum = 0.0;
+
+    for bound in bounds {
+        sum += bound;
+        if bound > max {
+            max = bound;
+        }
+    }
+
+    combine(max, sum, tiebreaker)
+}
+
+fn combine(max: Score, sum: Score, tiebreaker: f32) -> Score {
+    // Unknown bounds would otherwise turn into NaN
+    if max == Score::INFINITY {
+        max
+    } else {
+        max + (sum - max) * tiebreaker
+    }
+}
+
+/// A weight whose scorers can skip documents that score lower than
+/// a threshold, if `query` is a `DisMaxQuery` or a `BooleanQuery`
+/// with only optional clauses
+pub(crate) fn pruning_weight(
+    query: &dyn Query,
+    searcher: &Searcher,
+) -> Result<Option<DisMaxWeight>> {
+    let any = query.as_any();
+
+    let (disjuncts, tiebreaker, minimum_match) =
+        if let Some(dismax) = any.downcast_ref::<DisMaxQuery>() {
+            (
+                dismax.disjuncts.iter().collect::<Vec<_>>(),
+                dismax.tiebreaker,
+                dismax.minimum_match(),
+            )
+        } else if let Some(boolean) = any.downcast_ref::<BooleanQuery>() {
+            if boolean
+                .clauses()
+                .iter()
+                .any(|(occur, _query)| *occur != Occur::Should)
+            {
+                return Ok(None);
+            }
+
+            // Which are scored as their sum, same as a tiebreaker of 1
+            (
+                boolean
+                    .clauses()
+                    .iter()
+                    .map(|(_occur, query)| query)
+                    .collect(),
+                1.0,
+                1,
+            )
+        } else {
+            return Ok(None);
+        };
+
+    let mut weights = Vec::with_capacity(disjuncts.len());
+    let mut bounds = Vec::with_capacity(disjuncts.len());
+    for disjunct in disjuncts {
+        weights.push(disjunct.weight(searcher, true)?);
+        bounds.push(max_score(disjunct.as_ref(), searcher).unwrap_or(Score::INFINITY));
+    }
+
+    Ok(Some(DisMaxWeight::new(
+        weights,
+        bounds,
+        tiebreaker,
+        minimum_match,
+    )))
+}
+
+pub(crate) struct DisMaxWeight {
     weights: Vec<Box<dyn Weight>>,
+    bounds: Vec<Score>,
     tiebreaker: f32,
+    minimum_match: usize,
 }
 
 impl DisMaxWeight {
-    fn new(weights: Vec<Box<dyn Weight>>, tiebreaker: f32) -> Self {
+    fn new(
+        weights: Vec<Box<dyn Weight>>,
+        bounds: Vec<Score>,
+        tiebreaker: f32,
+        minimum_match: usize,
+    ) -> Self {
+        debug_assert_eq!(weights.len(), bounds.len());
         Self {
             weights,
+            bounds,
             tiebreaker,
+            minimum_match,
         }
     }
+
+    /// A scorer that can skip the documents scoring lower than the
+    /// threshold given via `DisMaxScorer::set_threshold`
+    pub fn pruning_scorer(&self, reader: &SegmentReader) -> Result<DisMaxScorer> {
+        let mut scorers = Vec::with_capacity(self.weights.len());
+        for (weight, bound) in self.weights.iter().zip(self.bounds.iter()) {
+            scorers.push((weight.scorer(reader, 1.0)?, *bound));
+        }
+
+        Ok(DisMaxScorer::with_bounds(
+            scorers,
+            self.tiebreaker,
+            self.minimum_match,
+        ))
+    }
 }
 
 impl Weight for DisMaxWeight {
@@ -74,12 +387,14 @@ impl Weight for DisMaxWeight {
         match self.weights.len() {
             0 => Ok(Box::new(EmptyScorer)),
             1 => self.weights.get(0).unwrap().scorer(reader, boost),
-            _ => Ok(Box::new(DisMaxScorer::new(
+            _ => Ok(Box::new(DisMaxScorer::with_bounds(
                 self.weights
                     .iter()
-                    .map(|w| w.scorer(reader, boost))
+                    .zip(self.bounds.iter())
+                    .map(|(w, bound)| Ok((w.scorer(reader, boost)?, *bound)))
                     .collect::<Result<Vec<_>>>()?,
                 self.tiebreaker,
+                self.minimum_match,
             ))),
         }
     }
@@ -93,65 +408,115 @@ impl Weight for DisMaxWeight {
 
         let mut explanation = Explanation::new(
             format!(
-                "DisMaxQuery. Score = max + (sum - max) * {}",
-                self.tiebreaker
+                "DisMaxQuery. Score = max + (sum - max) * {}. Minimum match = {}",
+                self.tiebreaker, self.minimum_match
             ),
             scorer.score(),
         );
 
-        for weight in &self.weights {
-            if let Ok(sub_explanation) = weight.explain(reader, doc) {
-                explanation.add_detail(sub_explanation);
+        let mut matches = Vec::new();
+        for (idx, weight) in self.weights.iter().enumerate() {
+            // Only disjuncts that match get explained, so any error
+            // from explaining is a real one
+            if weight.scorer(reader, 1.0)?.skip_next(doc) == SkipResult::Reached {
+                matches.push((idx, weight.explain(reader, doc)?));
+            }
+        }
+
+        // The first of the highest scoring matches, like the scorer
+        let (best, max) = matches.iter().fold((None, 0.0), |(best, max), (idx, sub)| {
+            if best.is_none() || sub.value() > max {
+                (Some(*idx), sub.value())
+            } else {
+                (best, max)
             }
+        });
+        let sum: Score = matches.iter().map(|(_idx, sub)| sub.value()).sum();
+
+        for (idx, sub) in matches {
+            let description = if Some(idx) == best {
+                format!("Disjunct #{}, the max", idx)
+            } else {
+                format!("Disjunct #{}", idx)
+            };
+
+            let mut disjunct = Explanation::new(description, sub.value());
+            disjunct.add_detail(sub);
+            explanation.add_detail(disjunct);
         }
 
+        explanation.add_const(
+            format!(
+                "Tiebreaker contribution = (sum - max) * {} with sum = {} and max = {}",
+                self.tiebreaker, sum, max
+            ),
+            (sum - max) * self.tiebreaker,
+        );
+
         Ok(explanation)
     }
 }
 
-struct DisMaxScorer {
-    scorers: Vec<Box<dyn Scorer>>,
+/// Every scorer is paired with an upper bound for its scores
+pub(crate) struct DisMaxScorer {
+    scorers: Vec<(Box<dyn Scorer>, Score)>,
     current: Option<DocId>,
     tiebreaker: f32,
+    minimum_match: usize,
+    threshold: Option<Score>,
 }
 
 impl DisMaxScorer {
+    #[cfg(test)]
     fn new(scorers: Vec<Box<dyn Scorer>>, tiebreaker: f32) -> Self {
+        Self::with_minimum_match(scorers, tiebreaker, 1)
+    }
+
+    #[cfg(test)]
+    fn with_minimum_match(
+        scorers: Vec<Box<dyn Scorer>>,
+        tiebreaker: f32,
+        minimum_match: usize,
+    ) -> Self {
+        Self::with_bounds(
+            scorers
+                .into_iter()
+                .map(|scorer| (scorer, Score::INFINITY))
+                .collect(),
+            tiebreaker,
+            minimum_match,
+        )
+    }
+
+    fn with_bounds(
+        scorers: Vec<(Box<dyn Scorer>, Score)>,
+        tiebreaker: f32,
+        minimum_match: usize,
+    ) -> Self {
         Self {
             scorers,
             tiebreaker,
             current: None,
+            minimum_match,
+            threshold: None,
         }
     }
-}
 
-impl Scorer for DisMaxScorer {
-    fn score(&mut self) -> Score {
-        let mut max = 0.0;
-        let mut sum = 0.0;
-
-        debug_assert!(self.current.is_some());
-        for scorer in &mut self.scorers {
-            if self.current.map_or(false, |d| scorer.doc() == d) {
-                let score = scorer.score();
-                sum += score;
-
-                if score > max {
-                    max = score;
-                }
-            }
-        }
-
-        max + (sum - max) * self.tiebreaker
+    /// Skip every document that can't score higher than `threshold`
+    ///
+    /// Documents scoring exactly `threshold` may or may not be skipped.
+    /// The threshold must never decrease.
+    pub fn set_threshold(&mut self, threshold: Score) {
+        debug_assert!(self.threshold.map_or(true, |current| current <= threshold));
+        self.threshold = Some(threshold);
     }
-}
 
-impl DocSet for DisMaxScorer {
-    fn advance(&mut self) -> bool {
+    /// Moves to the next document any of the scorers is on
+    fn advance_union(&mut self) -> bool {
         let mut next_target = None;
         let mut to_remove = Vec::new();
 
-        for (idx, scorer) in self.scorers.iter_mut().enumerate() {
+        for (idx, (scorer, _bound)) in self.scorers.iter_mut().enumerate() {
             // Advance every scorer that's on target or behind
             if self.current.map_or(true, |d| d >= scorer.doc()) && !scorer.advance() {
                 to_remove.push(idx);
@@ -176,6 +541,127 @@ impl DocSet for DisMaxScorer {
         }
     }
 
+    /// Drops the scorers `keep` yields false for
+    fn retain_scorers<F: FnMut(&mut dyn Scorer) -> bool>(&mut self, mut keep: F) {
+        let mut idx = 0;
+        while idx < self.scorers.len() {
+            if keep(self.scorers[idx].0.as_mut()) {
+                idx += 1;
+            } else {
+                self.scorers.remove(idx);
+            }
+        }
+    }
+
+    /// Moves to the next document that matches enough scorers and
+    /// may score higher than `threshold`, skipping every scorer
+    /// ahead past the ones that can't (as done by WAND)
+    fn advance_pruning(&mut self, threshold: Score) -> bool {
+        // Leave the current document behind
+        let current = self.current;
+        self.retain_scorers(|scorer| {
+            current.map_or(false, |d| scorer.doc() > d) || scorer.advance()
+        });
+
+        let mut order = Vec::with_capacity(self.scorers.len());
+        loop {
+            if self.scorers.len() < self.minimum_match {
+                return false;
+            }
+
+            order.clear();
+            order.extend(0..self.scorers.len());
+            let scorers = &self.scorers;
+            order.sort_by_key(|&idx| scorers[idx].0.doc());
+
+            // The first document that enough scorers could add up to
+            // a score above the threshold for
+            let mut max = 0.0;
+            let mut sum = 0.0;
+            let mut pivot = None;
+            for (pos, &idx) in order.iter().enumerate() {
+                let bound = scorers[idx].1;
+                sum += bound;
+                if bound > max {
+                    max = bound;
+                }
+
+                if pos + 1 >= self.minimum_match && combine(max, sum, self.tiebreaker) >= threshold
+                {
+                    pivot = Some(scorers[idx].0.doc());
+                    break;
+                }
+            }
+
+            let pivot = match pivot {
+                Some(pivot) => pivot,
+                None => return false,
+            };
+
+            if scorers[order[0]].0.doc() == pivot {
+                let matching = scorers
+                    .iter()
+                    .filter(|(scorer, _bound)| scorer.doc() == pivot)
+                    .count();
+
+                self.current = Some(pivot);
+                if matching >= self.minimum_match {
+                    return true;
+                }
+
+                self.retain_scorers(|scorer| scorer.doc() != pivot || scorer.advance());
+            } else {
+                // Nothing before the pivot is worth looking at
+                self.retain_scorers(|scorer| {
+                    scorer.doc() >= pivot || scorer.skip_next(pivot) != SkipResult::End
+                });
+            }
+        }
+    }
+}
+
+impl Scorer for DisMaxScorer {
+    fn score(&mut self) -> Score {
+        let mut max = 0.0;
+        let mut sum = 0.0;
+
+        debug_assert!(self.current.is_some());
+        for (scorer, _bound) in &mut self.scorers {
+            if self.current.map_or(false, |d| scorer.doc() == d) {
+                let score = scorer.score();
+                sum += score;
+
+                if score > max {
+                    max = score;
+                }
+            }
+        }
+
+        max + (sum - max) * self.tiebreaker
+    }
+}
+
+impl DocSet for DisMaxScorer {
+    fn advance(&mut self) -> bool {
+        if let Some(threshold) = self.threshold {
+            return self.advance_pruning(threshold);
+        }
+
+        while self.scorers.len() >= self.minimum_match && self.advance_union() {
+            let doc = self.doc();
+            let matching = self
+                .scorers
+                .iter()
+                .filter(|(s, _bound)| s.doc() == doc)
+                .count();
+
+            if matching >= self.minimum_match {
+                return true;
+            }
+        }
+        false
+    }
+
     fn doc(&self) -> tantivy::DocId {
         debug_assert!(self.current.is_some());
         self.current.unwrap_or(0)
@@ -193,10 +679,10 @@ mod tests {
     use std::{num::Wrapping, ops::Range};
 
     use tantivy::{
+        collector::TopDocs,
         doc,
-        query::TermQuery,
         schema::{IndexRecordOption, SchemaBuilder, TEXT},
-        DocAddress, Index, Term,
+        DocAddress, Index,
     };
 
     // XXX ConstScorer::from(VecDocSet::from(...)), but I can't seem
@@ -256,6 +742,149 @@ mod tests {
         assert!(!dismax.advance(), "scorer should have ended by now");
     }
 
+    #[test]
+    fn scorer_requires_minimum_match() {
+        let scorers = vec![test_scorer(0..10), test_scorer(5..20), test_scorer(8..30)];
+
+        let mut dismax = DisMaxScorer::with_minimum_match(scorers, 1.0, 2);
+
+        for i in 5..20 {
+            assert!(dismax.advance(), "failed advance at i={}", i);
+            assert_eq!(i, dismax.doc());
+        }
+        assert!(!dismax.advance(), "scorer should have ended by now");
+
+        let scorers = vec![test_scorer(0..10), test_scorer(5..20), test_scorer(8..30)];
+        let mut dismax = DisMaxScorer::with_minimum_match(scorers, 1.0, 3);
+
+        assert!(dismax.advance());
+        assert_eq!(8, dismax.doc());
+        assert!(dismax.advance());
+        assert_eq!(9, dismax.doc());
+        assert!(!dismax.advance(), "scorer should have ended by now");
+    }
+
+    #[test]
+    fn minimum_should_match() {
+        use MinimumShouldMatch::*;
+
+        assert_eq!(Ok(Absolute(3)), "3".parse());
+        assert_eq!(Ok(Percentage(75)), "75%".parse());
+        for invalid in &["", "%", "-1", "101%", "1.5", "50 %"] {
+            assert!(invalid.parse::<MinimumShouldMatch>().is_err());
+        }
+
+        assert_eq!("75%", Percentage(75).to_string());
+
+        assert_eq!(3, Absolute(3).resolve(5));
+        assert_eq!(2, Absolute(3).resolve(2));
+        assert_eq!(1, Absolute(0).resolve(2));
+        assert_eq!(3, Percentage(75).resolve(5));
+        assert_eq!(1, Percentage(10).resolve(5));
+        assert_eq!(5, Percentage(100).resolve(5));
+    }
+
+    #[test]
+    fn scorer_skips_below_threshold() {
+        let bounded = |ranges: Vec<Range<DocId>>| {
+            ranges
+                .into_iter()
+                .map(|range| (test_scorer(range), 1.0))
+                .collect::<Vec<_>>()
+        };
+
+        // VecScorer always yields 1, so with a tiebreaker of 1 the
+        // threshold tells how many scorers must match
+        let mut dismax = DisMaxScorer::with_bounds(bounded(vec![0..10, 5..20, 8..30]), 1.0, 1);
+        dismax.set_threshold(1.5);
+        for i in 5..20 {
+            assert!(dismax.advance(), "failed advance at i={}", i);
+            assert_eq!(i, dismax.doc());
+        }
+        assert!(!dismax.advance(), "scorer should have ended by now");
+
+        let mut dismax = DisMaxScorer::with_bounds(bounded(vec![0..10, 5..20, 8..30]), 1.0, 1);
+        assert!(dismax.advance());
+        assert_eq!(0, dismax.doc());
+        // Thresholds apply from the next advance on
+        dismax.set_threshold(2.5);
+        assert!(dismax.advance());
+        assert_eq!(8, dismax.doc());
+        assert!(dismax.advance());
+        assert_eq!(9, dismax.doc());
+        assert!(!dismax.advance(), "scorer should have ended by now");
+
+        // Minimum match still applies
+        let mut dismax = DisMaxScorer::with_bounds(bounded(vec![0..10, 5..20, 8..30]), 0.0, 3);
+        dismax.set_threshold(0.5);
+        assert!(dismax.advance());
+        assert_eq!(8, dismax.doc());
+        assert!(dismax.advance());
+        assert_eq!(9, dismax.doc());
+        assert!(!dismax.advance(), "scorer should have ended by now");
+
+        // And unknown bounds never skip anything
+        let mut dismax = DisMaxScorer::new(vec![test_scorer(0..3), test_scorer(2..4)], 0.0);
+        dismax.set_threshold(100.0);
+        for i in 0..4 {
+            assert!(dismax.advance(), "failed advance at i={}", i);
+            assert_eq!(i, dismax.doc());
+        }
+        assert!(!dismax.advance(), "scorer should have ended by now");
+    }
+
+    #[test]
+    fn score_bounds() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "foo foo foo foo foo foo"));
+        writer.add_document(doc!(field => "foo bar"));
+        writer.add_document(doc!(field => "bar"));
+        writer.add_document(doc!(field => "baz"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let term = |text| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, text),
+                IndexRecordOption::WithFreqs,
+            ))
+        };
+
+        let foo = max_score(term("foo").as_ref(), &searcher).expect("bounded");
+        let bar = max_score(term("bar").as_ref(), &searcher).expect("bounded");
+
+        let dismax = DisMaxQuery::new(vec![term("foo"), term("bar")], 0.5);
+        let top = searcher.search(&dismax, &TopDocs::with_limit(4))?;
+        assert!(top.iter().all(|(score, _addr)| *score < foo.max(bar) * 1.5));
+        assert_eq!(
+            Some(foo.max(bar) + foo.min(bar) * 0.5),
+            max_score(&dismax, &searcher)
+        );
+
+        let boosted = BoostedQuery::new(term("foo"), 2.0);
+        assert_eq!(Some(foo * 2.0), max_score(&boosted, &searcher));
+        let top = searcher.search(&boosted, &TopDocs::with_limit(1))?;
+        assert!(top[0].0 < foo * 2.0);
+
+        let boolean = BooleanQuery::from(vec![
+            (Occur::Should, term("foo")),
+            (Occur::Must, term("bar")),
+            (Occur::MustNot, term("baz")),
+        ]);
+        assert_eq!(Some(foo + bar), max_score(&boolean, &searcher));
+
+        let unknown = BoostQuery::new(term("foo"), 2.0);
+        assert_eq!(None, max_score(&unknown, &searcher));
+
+        Ok(())
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn tiebreaker() {
@@ -310,7 +939,10 @@ mod tests {
         let reader = index.reader()?;
         let searcher = reader.searcher();
 
-        let dismax = DisMaxQuery::new(vec![Box::new(foo_query), Box::new(bar_query)], 0.0);
+        let dismax = DisMaxQuery::new(
+            vec![Box::new(foo_query.clone()), Box::new(bar_query.clone())],
+            0.0,
+        );
 
         let baz_doc = DocAddress(0, 3);
         assert!(
@@ -324,6 +956,41 @@ mod tests {
             assert!(explanation.to_pretty_json().contains("DisMaxQuery"));
         }
 
+        let dismax = DisMaxQuery::new(
+            vec![Box::new(foo_query.clone()), Box::new(bar_query.clone())],
+            0.5,
+        );
+        let explanation = dismax.explain(&searcher, DocAddress(0, 2))?;
+        let json: serde_json::Value = serde_json::from_str(&explanation.to_pretty_json()).unwrap();
+
+        let details = json["details"].as_array().expect("has details");
+        assert_eq!(3, details.len());
+
+        let disjunct_score = |idx: usize| details[idx]["value"].as_f64().unwrap() as f32;
+        let (foo_score, bar_score) = (disjunct_score(0), disjunct_score(1));
+        let (max, min) = (foo_score.max(bar_score), foo_score.min(bar_score));
+
+        // Disjuncts carry the explanation of their queries
+        let foo_explanation = foo_query.explain(&searcher, DocAddress(0, 2))?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&foo_explanation.to_pretty_json()).unwrap(),
+            details[0]["details"][0]
+        );
+
+        let best = if foo_score >= bar_score { 0 } else { 1 };
+        assert!(details[best]["description"]
+            .as_str()
+            .unwrap()
+            .ends_with("the max"));
+        assert!(!details[1 - best]["description"]
+            .as_str()
+            .unwrap()
+            .ends_with("the max"));
+
+        let contribution = details[2]["value"].as_f64().unwrap() as f32;
+        assert!((min * 0.5 - contribution).abs() < 1e-6);
+        assert!((explanation.value() - (max + contribution)).abs() < 1e-6);
+
         Ok(())
     }
 }
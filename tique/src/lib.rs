@@ -40,6 +40,22 @@
 //! # Ok::<(), tantivy::TantivyError>(())
 //!```
 //!
+//! ## suggest
+//!
+//! Spelling suggestions for words that can't be found in your index,
+//! using the most frequent words that are a few typos away.
+//!
+//!```no_run
+//! # use tantivy::{Index, schema::{Schema, TEXT}};
+//! # use tique::suggest::Suggester;
+//! # let mut builder = Schema::builder();
+//! # let title = builder.add_text_field("title", TEXT);
+//! # let index = Index::create_in_ram(builder.build());
+//! let suggester = Suggester::new(&index, vec![title])?;
+//! let did_you_mean = suggester.suggest("bacn pancakes");
+//! # Ok::<(), tantivy::TantivyError>(())
+//!```
+//!
 //! ## QueryParser
 //!
 //! A query parser with a simple grammar geared towards usage by
@@ -68,12 +84,16 @@
 //! ```
 
 pub mod conditional_collector;
+pub mod suggest;
 pub mod topterms;
 
 #[cfg(feature = "queryparser")]
 mod queryparser;
 #[cfg(feature = "queryparser")]
-pub use queryparser::QueryParser;
+pub use queryparser::{
+    Clause, Diagnostic, LimitExceeded, Occurrence, ParseReport, QueryAst, QueryLimits, QueryParser,
+    Synonyms,
+};
 
 mod dismax;
-pub use dismax::DisMaxQuery;
+pub use dismax::{DisMaxQuery, MinimumShouldMatch};
@@ -0,0 +1,444 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Bound,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tantivy::query::Occur;
+
+use super::raw::{RawClause, RawGroup, RawQuery, RawRange};
+
+/// A query as understood by the `QueryParser`, before it gets
+/// turned into a tantivy query
+///
+/// Its `Display` implementation renders the query back as text in a
+/// canonical form: operators like `AND` and `NOT` become `+` and `-`
+/// and any ignored whitespace is gone, so parsing it again yields
+/// the same `QueryAst`. When `default_occur` is `Must`, optional
+/// clauses are joined to their neighbours with `OR`, so the text
+/// means the same to parsers with either default.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QueryAst {
+    /// Every top-level clause, in the order they appear in the input
+    pub clauses: Vec<Clause>,
+    /// What clauses without a prefix mean to the parser that
+    /// produced this query
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub default_occur: Occurrence,
+}
+
+/// Whether a clause must, must not or may match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Occurrence {
+    /// Matching is optional, but improves ranking: `bacon`
+    #[default]
+    Should,
+    /// Matching is required: `+bacon`
+    Must,
+    /// Matching is prohibited: `-bacon`
+    MustNot,
+}
+
+/// A single item of a query
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum Clause {
+    /// A word, like `bacon`, `title:bacon`, `parmesean~1` or `bacon^2`
+    Term {
+        /// The word itself
+        text: String,
+        /// The field to search in instead of the default ones
+        field: Option<String>,
+        /// Whether it must match
+        occur: Occurrence,
+        /// Maximum edit distance for fuzzy matching
+        fuzziness: Option<u8>,
+        /// How important it is compared to the rest of the query
+        boost: Option<f32>,
+    },
+    /// A sequence of words, like `"deep fry"` or `"chicken thighs"~2`
+    Phrase {
+        /// The words between the quotes
+        text: String,
+        /// The field to search in instead of the default ones
+        field: Option<String>,
+        /// Whether it must match
+        occur: Occurrence,
+        /// How far apart the words may be
+        slop: Option<u32>,
+        /// How important it is compared to the rest of the query
+        boost: Option<f32>,
+    },
+    /// A word pattern, like `choco*`
+    Wildcard {
+        /// The pattern, with `*` standing for any sequence of characters
+        pattern: String,
+        /// The field to search in instead of the default ones
+        field: Option<String>,
+        /// Whether it must match
+        occur: Occurrence,
+        /// How important it is compared to the rest of the query
+        boost: Option<f32>,
+    },
+    /// A comparison against a numeric field, like `calories:<400`
+    ///
+    /// At least one of the bounds is expected to be set: there's
+    /// no text that parses into a range without any.
+    Range {
+        /// The numeric field being compared against
+        field: String,
+        /// Lower bound, as written in the input
+        lower: Bound<String>,
+        /// Upper bound, as written in the input
+        upper: Bound<String>,
+        /// Whether it must match
+        occur: Occurrence,
+    },
+    /// A parenthesized sequence of clauses
+    Group {
+        /// The clauses inside the parentheses
+        clauses: Vec<Clause>,
+        /// Whether it must match
+        occur: Occurrence,
+    },
+}
+
+impl From<Occur> for Occurrence {
+    fn from(occur: Occur) -> Self {
+        match occur {
+            Occur::Should => Occurrence::Should,
+            Occur::Must => Occurrence::Must,
+            Occur::MustNot => Occurrence::MustNot,
+        }
+    }
+}
+
+impl From<Occurrence> for Occur {
+    fn from(occur: Occurrence) -> Self {
+        match occur {
+            Occurrence::Should => Occur::Should,
+            Occurrence::Must => Occur::Must,
+            Occurrence::MustNot => Occur::MustNot,
+        }
+    }
+}
+
+impl<'a> From<&RawQuery<'a>> for Clause {
+    fn from(raw: &RawQuery<'a>) -> Self {
+        let field = raw.field_name.map(str::to_owned);
+        let occur = raw.occur.into();
+
+        if raw.is_phrase {
+            Clause::Phrase {
+                text: raw.input.to_owned(),
+                field,
+                occur,
+                slop: raw.slop,
+                boost: raw.boost,
+            }
+        } else if raw.is_wildcard {
+            Clause::Wildcard {
+                pattern: raw.input.to_owned(),
+                field,
+                occur,
+                boost: raw.boost,
+            }
+        } else {
+            Clause::Term {
+                text: raw.input.to_owned(),
+                field,
+                occur,
+                fuzziness: raw.fuzziness,
+                boost: raw.boost,
+            }
+        }
+    }
+}
+
+impl<'a> From<&RawRange<'a>> for Clause {
+    fn from(raw: &RawRange<'a>) -> Self {
+        Clause::Range {
+            field: raw.field_name.to_owned(),
+            lower: to_owned_bound(raw.lower),
+            upper: to_owned_bound(raw.upper),
+            occur: raw.occur.into(),
+        }
+    }
+}
+
+impl<'a> From<&RawGroup<'a>> for Clause {
+    fn from(raw: &RawGroup<'a>) -> Self {
+        Clause::Group {
+            clauses: raw.clauses.iter().map(Clause::from).collect(),
+            occur: raw.occur.into(),
+        }
+    }
+}
+
+impl<'a> From<&RawClause<'a>> for Clause {
+    fn from(raw: &RawClause<'a>) -> Self {
+        match raw {
+            RawClause::Query(query) => query.into(),
+            RawClause::Range(range) => range.into(),
+            RawClause::Group(group) => group.into(),
+        }
+    }
+}
+
+fn to_owned_bound(bound: Bound<&str>) -> Bound<String> {
+    match bound {
+        Bound::Included(value) => Bound::Included(value.to_owned()),
+        Bound::Excluded(value) => Bound::Excluded(value.to_owned()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl Clause {
+    fn occur(&self) -> Occurrence {
+        match self {
+            Clause::Term { occur, .. }
+            | Clause::Phrase { occur, .. }
+            | Clause::Wildcard { occur, .. }
+            | Clause::Range { occur, .. }
+            | Clause::Group { occur, .. } => *occur,
+        }
+    }
+}
+
+impl Display for QueryAst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_clauses(f, &self.clauses, self.default_occur)
+    }
+}
+
+fn write_clauses(
+    f: &mut Formatter<'_>,
+    clauses: &[Clause],
+    default_occur: Occurrence,
+) -> fmt::Result {
+    for (idx, clause) in clauses.iter().enumerate() {
+        if idx > 0 {
+            // A bare clause would be required, so optional ones are
+            // kept optional by joining them with `OR`
+            if default_occur == Occurrence::Must
+                && (clause.occur() == Occurrence::Should
+                    || clauses[idx - 1].occur() == Occurrence::Should)
+            {
+                write!(f, " OR ")?;
+            } else {
+                write!(f, " ")?;
+            }
+        }
+        write_clause(f, clause, default_occur)?;
+    }
+    Ok(())
+}
+
+impl Display for Occurrence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Occurrence::Should => Ok(()),
+            Occurrence::Must => write!(f, "+"),
+            Occurrence::MustNot => write!(f, "-"),
+        }
+    }
+}
+
+fn write_field(f: &mut Formatter<'_>, field: &Option<String>) -> fmt::Result {
+    if let Some(name) = field {
+        write!(f, "{}:", name)?;
+    }
+    Ok(())
+}
+
+fn write_boost(f: &mut Formatter<'_>, boost: Option<f32>) -> fmt::Result {
+    if let Some(boost) = boost {
+        write!(f, "^{}", boost)?;
+    }
+    Ok(())
+}
+
+impl Display for Clause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_clause(f, self, Occurrence::Should)
+    }
+}
+
+fn write_clause(f: &mut Formatter<'_>, clause: &Clause, default_occur: Occurrence) -> fmt::Result {
+    match clause {
+        Clause::Term {
+            text,
+            field,
+            occur,
+            fuzziness,
+            boost,
+        } => {
+            write!(f, "{}", occur)?;
+            write_field(f, field)?;
+            write!(f, "{}", text)?;
+            if let Some(distance) = fuzziness {
+                write!(f, "~{}", distance)?;
+            }
+            write_boost(f, *boost)
+        }
+        Clause::Phrase {
+            text,
+            field,
+            occur,
+            slop,
+            boost,
+        } => {
+            write!(f, "{}", occur)?;
+            write_field(f, field)?;
+            write!(f, "\"{}\"", text)?;
+            if let Some(slop) = slop {
+                write!(f, "~{}", slop)?;
+            }
+            write_boost(f, *boost)
+        }
+        Clause::Wildcard {
+            pattern,
+            field,
+            occur,
+            boost,
+        } => {
+            write!(f, "{}", occur)?;
+            write_field(f, field)?;
+            write!(f, "{}", pattern)?;
+            write_boost(f, *boost)
+        }
+        Clause::Range {
+            field,
+            lower,
+            upper,
+            occur,
+        } => write_range(f, field, lower, upper, *occur),
+        Clause::Group { clauses, occur } => {
+            write!(f, "{}(", occur)?;
+            write_clauses(f, clauses, default_occur)?;
+            write!(f, ")")
+        }
+    }
+}
+
+fn write_range(
+    f: &mut Formatter<'_>,
+    field: &str,
+    lower: &Bound<String>,
+    upper: &Bound<String>,
+    occur: Occurrence,
+) -> fmt::Result {
+    use Bound::*;
+
+    match (lower, upper) {
+        (Unbounded, Excluded(end)) => write!(f, "{}{}:<{}", occur, field, end),
+        (Unbounded, Included(end)) => write!(f, "{}{}:<={}", occur, field, end),
+        (Excluded(start), Unbounded) => write!(f, "{}{}:>{}", occur, field, start),
+        (Included(start), Unbounded) => write!(f, "{}{}:>={}", occur, field, start),
+        (Included(start), Excluded(end)) => write!(f, "{}{}:{}..{}", occur, field, start, end),
+        (Included(start), Included(end)) => write!(f, "{}{}:{}..={}", occur, field, start, end),
+        (Excluded(_), _) => {
+            // There's no syntax for this one, so it's split in two. Since
+            // comparisons are always required, grouping them keeps the meaning
+            let occur = if occur == Occurrence::MustNot {
+                Occurrence::MustNot
+            } else {
+                Occurrence::Must
+            };
+            write!(f, "{}(", occur)?;
+            write_range(f, field, lower, &Unbounded, Occurrence::Must)?;
+            write!(f, " ")?;
+            write_range(f, field, &Unbounded, upper, Occurrence::Must)?;
+            write!(f, ")")
+        }
+        // Never produced by the parser: `field:..` is searched as text
+        (Unbounded, Unbounded) => write!(f, "{}{}:..", occur, field),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quickcheck::QuickCheck;
+
+    use crate::queryparser::raw::parse_query;
+
+    fn parse(input: &str) -> QueryAst {
+        let (_, parsed) = parse_query(input, &true).unwrap();
+        QueryAst {
+            clauses: parsed.iter().map(Clause::from).collect(),
+            default_occur: Occurrence::Should,
+        }
+    }
+
+    #[test]
+    fn canonical_form() {
+        for (input, canonical) in &[
+            ("bacon", "bacon"),
+            ("  +bacon   -ham ", "+bacon -ham"),
+            ("bacon AND ham NOT eggs", "+bacon +ham -eggs"),
+            ("parmesean~ cheddar~2^1.5", "parmesean~1 cheddar~2^1.5"),
+            ("title:\"deep fry\"~2^3", "title:\"deep fry\"~2^3"),
+            ("-choco*^0.5", "-choco*^0.5"),
+            (
+                "calories:<400 fat:>=1.5 time:10..=30",
+                "calories:<400 fat:>=1.5 time:10..=30",
+            ),
+            (
+                "(bacon  pancetta) -(egg \"fried egg\")",
+                "(bacon pancetta) -(egg \"fried egg\")",
+            ),
+        ] {
+            let ast = parse(input);
+            assert_eq!(*canonical, ast.to_string());
+            assert_eq!(ast, parse(&ast.to_string()));
+        }
+    }
+
+    #[test]
+    fn ranges_without_syntax_are_split() {
+        let ast = QueryAst {
+            clauses: vec![Clause::Range {
+                field: "calories".to_owned(),
+                lower: Bound::Excluded("100".to_owned()),
+                upper: Bound::Included("400".to_owned()),
+                occur: Occurrence::Should,
+            }],
+            default_occur: Occurrence::Should,
+        };
+
+        assert_eq!("+(+calories:>100 +calories:<=400)", ast.to_string());
+    }
+
+    #[test]
+    fn display_is_stable() {
+        fn prop(input: String) -> bool {
+            // The canonical form is only guaranteed to mean the same
+            // thing, so what must hold is that it's a fixed point
+            let canonical = parse(&input).to_string();
+            canonical == parse(&canonical).to_string()
+        }
+
+        QuickCheck::new().quickcheck(prop as fn(String) -> bool);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let ast = parse("+title:bacon~1^2 -\"fried egg\"~1 choco* calories:..400 (a b)");
+        let json = serde_json::to_string(&ast).unwrap();
+        assert_eq!(ast, serde_json::from_str(&json).unwrap());
+    }
+}
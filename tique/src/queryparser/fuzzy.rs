@@ -0,0 +1,40 @@
+use std::collections::BTreeSet;
+
+use tantivy::{
+    query::{FuzzyTermQuery, Query, TermQuery, Weight},
+    schema::IndexRecordOption,
+    Result, Searcher, Term,
+};
+
+/// A TermQuery that turns into a FuzzyTermQuery when the term can't
+/// be found in the index
+///
+/// The decision is taken when creating the weight, so it always
+/// reflects the documents visible to the searcher being used.
+#[derive(Debug, Clone)]
+pub(crate) struct AutoFuzzyQuery {
+    term: Term,
+    distance: u8,
+}
+
+impl AutoFuzzyQuery {
+    pub fn new(term: Term, distance: u8) -> Self {
+        Self { term, distance }
+    }
+}
+
+impl Query for AutoFuzzyQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        if searcher.doc_freq(&self.term) == 0 {
+            FuzzyTermQuery::new(self.term.clone(), self.distance, true)
+                .weight(searcher, scoring_enabled)
+        } else {
+            TermQuery::new(self.term.clone(), IndexRecordOption::WithFreqs)
+                .weight(searcher, scoring_enabled)
+        }
+    }
+
+    fn query_terms(&self, term_set: &mut BTreeSet<Term>) {
+        term_set.insert(self.term.clone());
+    }
+}
@@ -0,0 +1,202 @@
+use std::{error::Error, fmt};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Bounds on how expensive the query for a given input may get
+///
+/// Every limit is optional and disabled by default. Parsing never
+/// fails because of them: `QueryParser::parse` keeps as much of the
+/// input as fits and drops the rest, while `QueryParser::check_limits`
+/// tells whether anything would be dropped, for callers that would
+/// rather reject the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct QueryLimits {
+    /// How many characters of the input are parsed
+    pub max_input_length: Option<usize>,
+    /// How many words, phrases and comparisons are searched for,
+    /// counting the ones within parentheses but not the groups
+    pub max_clauses: Option<usize>,
+    /// How many terms may be searched for in total. Every analyzed
+    /// word counts once per field it is searched in, as does every
+    /// word of its synonyms and every pattern like `choco*`
+    pub max_term_queries: Option<usize>,
+    /// How many (analyzed) words of a phrase are searched for
+    pub max_phrase_length: Option<usize>,
+}
+
+/// A limit from `QueryLimits` that an input goes over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The input is longer than this many characters
+    InputLength(usize),
+    /// The input has more than this many clauses
+    Clauses(usize),
+    /// The input searches for more than this many terms
+    TermQueries(usize),
+    /// A phrase has more than this many words
+    PhraseLength(usize),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::InputLength(max) => {
+                write!(f, "Query longer than {} characters", max)
+            }
+            LimitExceeded::Clauses(max) => write!(f, "Query with more than {} items", max),
+            LimitExceeded::TermQueries(max) => {
+                write!(f, "Query searching for more than {} terms", max)
+            }
+            LimitExceeded::PhraseLength(max) => {
+                write!(f, "Phrase with more than {} words", max)
+            }
+        }
+    }
+}
+
+impl Error for LimitExceeded {}
+
+impl QueryLimits {
+    /// The part of `input` within `max_input_length`
+    pub(crate) fn truncate_input<'a>(&self, input: &'a str) -> (&'a str, Option<LimitExceeded>) {
+        let max = match self.max_input_length {
+            Some(max) => max,
+            None => return (input, None),
+        };
+
+        match input.char_indices().nth(max) {
+            Some((end, _char)) => (&input[..end], Some(LimitExceeded::InputLength(max))),
+            None => (input, None),
+        }
+    }
+}
+
+/// What is left of the clause and term limits while going through
+/// the clauses of a query
+pub(crate) struct Budget {
+    clauses: Option<usize>,
+    term_queries: Option<usize>,
+    limits: QueryLimits,
+    /// Every limit exceeded so far, in the order they were found
+    pub exceeded: Vec<LimitExceeded>,
+    /// Whether a clause didn't fit, so none of the following do
+    pub exhausted: bool,
+}
+
+impl Budget {
+    pub fn new(limits: QueryLimits) -> Self {
+        Self {
+            clauses: limits.max_clauses,
+            term_queries: limits.max_term_queries,
+            limits,
+            exceeded: Vec::new(),
+            exhausted: false,
+        }
+    }
+
+    /// Takes a clause costing `cost` term queries out of the budget,
+    /// yielding whether it fits
+    pub fn take(&mut self, cost: usize) -> bool {
+        if self.exhausted {
+            return false;
+        }
+
+        if self.clauses == Some(0) {
+            self.exhaust(LimitExceeded::Clauses(self.limits.max_clauses.unwrap_or(0)));
+            return false;
+        }
+
+        if let Some(remaining) = self.term_queries {
+            if cost > remaining {
+                self.exhaust(LimitExceeded::TermQueries(
+                    self.limits.max_term_queries.unwrap_or(0),
+                ));
+                return false;
+            }
+            self.term_queries = Some(remaining - cost);
+        }
+
+        if let Some(remaining) = self.clauses.as_mut() {
+            *remaining -= 1;
+        }
+
+        true
+    }
+
+    /// Records a phrase with `length` words, which gets shortened
+    /// instead of dropped
+    pub fn check_phrase(&mut self, length: usize) {
+        if let Some(max) = self.limits.max_phrase_length {
+            if length > max {
+                self.exceeded.push(LimitExceeded::PhraseLength(max));
+            }
+        }
+    }
+
+    fn exhaust(&mut self, exceeded: LimitExceeded) {
+        self.exceeded.push(exceeded);
+        self.exhausted = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_truncation() {
+        let mut limits = QueryLimits::default();
+        assert_eq!(
+            ("crème brûlée", None),
+            limits.truncate_input("crème brûlée")
+        );
+
+        limits.max_input_length = Some(4);
+        assert_eq!(
+            ("crèm", Some(LimitExceeded::InputLength(4))),
+            limits.truncate_input("crème brûlée")
+        );
+        assert_eq!(("crèm", None), limits.truncate_input("crèm"));
+    }
+
+    #[test]
+    fn budget() {
+        let mut budget = Budget::new(QueryLimits {
+            max_clauses: Some(3),
+            max_term_queries: Some(5),
+            max_phrase_length: Some(2),
+            ..QueryLimits::default()
+        });
+
+        assert!(budget.take(2));
+        budget.check_phrase(2);
+        assert!(budget.take(3));
+        budget.check_phrase(3);
+        assert!(!budget.take(1));
+        assert!(budget.exhausted);
+        // Nothing fits once exhausted
+        assert!(!budget.take(0));
+
+        assert_eq!(
+            vec![
+                LimitExceeded::PhraseLength(2),
+                LimitExceeded::TermQueries(5)
+            ],
+            budget.exceeded
+        );
+
+        let mut budget = Budget::new(QueryLimits {
+            max_clauses: Some(1),
+            ..QueryLimits::default()
+        });
+        assert!(budget.take(100));
+        assert!(!budget.take(0));
+        assert_eq!(vec![LimitExceeded::Clauses(1)], budget.exceeded);
+    }
+}
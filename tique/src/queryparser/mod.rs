@@ -1,4 +1,15 @@
+mod ast;
+mod fuzzy;
+mod limits;
 mod parser;
+mod proximity;
 mod raw;
+mod report;
+mod synonyms;
+mod wildcard;
 
+pub use ast::{Clause, Occurrence, QueryAst};
+pub use limits::{LimitExceeded, QueryLimits};
 pub use parser::QueryParser;
+pub use report::{Diagnostic, ParseReport};
+pub use synonyms::Synonyms;
@@ -1,12 +1,27 @@
-use super::raw::{parse_query, FieldNameValidator, RawQuery};
-use crate::DisMaxQuery;
+use super::{
+    ast::{Clause, QueryAst},
+    fuzzy::AutoFuzzyQuery,
+    limits::{Budget, LimitExceeded, QueryLimits},
+    proximity::SloppyPhraseQuery,
+    raw::{
+        parse_query, FieldNameValidator, RawClause, RawQuery, RawRange, FIELD_SEP, MAX_FUZZINESS,
+    },
+    report::{Diagnostic, ParseReport},
+    synonyms::Synonyms,
+    wildcard::WildcardQuery,
+};
+use crate::{dismax::BoostedQuery, DisMaxQuery, MinimumShouldMatch};
+
+use std::{collections::HashMap, ops::Bound, str::FromStr};
 
 use tantivy::{
     self,
-    query::{AllQuery, BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, TermQuery},
-    schema::{Field, IndexRecordOption},
+    query::{
+        AllQuery, BooleanQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery, TermQuery,
+    },
+    schema::{Field, IndexRecordOption, Schema, Type},
     tokenizer::TextAnalyzer,
-    Index, Result, Term,
+    Index, Result, Searcher, TantivyError, Term,
 };
 
 /// Parse queries from arbitrary end-user input
@@ -33,9 +48,80 @@ use tantivy::{
 /// Which ends up prohibiting documents with "egg" in the "ingredients"
 /// field from showing up.
 ///
+/// Items can be grouped with parentheses and combined with the `AND`,
+/// `OR` and `NOT` operators:
+///
+/// > (bacon OR pancetta) AND NOT egg
+///
+/// `a AND b` is the same as `+a +b`, `NOT a` is the same as `-a` and
+/// `a OR b` is the same as `a b` (unless items are required by default,
+/// see `set_default_occur`). Input that can't be understood, like
+/// unbalanced parentheses, groups nested more than 16 levels deep or
+/// a dangling operator, is searched for as regular words instead of
+/// leading to an error.
+///
+/// Misspellings can be tolerated by suffixing a word with `~` and,
+/// optionally, the maximum number of edits (up to 2) needed to find
+/// a match:
+///
+/// > parmesean~ zuchini~2
+///
+/// Which matches documents with "parmesan" and "zucchini".
+///
+/// And words can be completed with `*`, which stands for any sequence
+/// of characters:
+///
+/// > choco* choc*late
+///
+/// A `*` at the start of a word is not special. Patterns are only
+/// lowercased, not analyzed, and match at most 50 distinct words
+/// (configurable via `set_max_expansions`), preferring the most
+/// frequent ones.
+///
+/// Phrases can match words that aren't exactly next to each other
+/// by suffixing them with `~` and the maximum number of moves needed
+/// to put the words in place (the slop):
+///
+/// > "chicken thighs"~1
+///
+/// Which also matches "chicken and thighs". The default slop can be
+/// changed via `set_default_slop`.
+///
+/// Any word or phrase can be made more (or less) important with `^`:
+///
+/// > bacon^2 cheese "deep fry"^0.5
+///
+/// Which combines with the field boost configured via `set_boost` by
+/// multiplying both.
+///
+/// Numeric fields registered via `add_range_field` can be compared
+/// against:
+///
+/// > pasta calories:<400 total_time:10..30
+///
+/// Accepted forms are `<N`, `<=N`, `>N`, `>=N`, `A..B` (which does
+/// not include `B`), `A..=B`, `A..` and `..B`. Unlike words, these
+/// comparisons are always required unless prohibited with `-`.
+///
+/// Words and phrases can also match their synonyms, as configured
+/// per field via `set_synonyms`.
+///
+/// Optional items only need to match one at a time by default, so a
+/// long query like "chicken garlic lemon thyme potato" also finds
+/// documents that only mention "potato". `set_minimum_should_match`
+/// changes that to requiring a number (or a percentage) of them.
+///
+/// Long inputs can be kept from turning into expensive queries via
+/// `set_limits`.
+///
 pub struct QueryParser {
+    schema: Schema,
     state: Vec<(Option<String>, Option<f32>, Interpreter)>,
     default_indices: Vec<usize>,
+    ranges: Vec<(String, Field, Type)>,
+    minimum_should_match: Option<MinimumShouldMatch>,
+    default_occur: Occur,
+    limits: QueryLimits,
 }
 
 impl QueryParser {
@@ -52,16 +138,18 @@ impl QueryParser {
         let mut parser = QueryParser {
             default_indices: (0..fields.len()).collect(),
             state: Vec::with_capacity(fields.len()),
+            ranges: Vec::new(),
+            schema: schema.clone(),
+            minimum_should_match: None,
+            default_occur: Occur::Should,
+            limits: QueryLimits::default(),
         };
 
         for field in fields {
             parser.state.push((
                 Some(schema.get_field_name(field).to_owned()),
                 None,
-                Interpreter {
-                    field,
-                    analyzer: index.tokenizer_for_field(field)?,
-                },
+                Interpreter::new(field, index.tokenizer_for_field(field)?),
             ));
         }
 
@@ -99,6 +187,105 @@ impl QueryParser {
         }
     }
 
+    /// Search for terms that can't be found in the index with fuzzy
+    /// matching
+    ///
+    /// When set, every word in a query that appears in no document is
+    /// treated as if it was written as `word~distance`. Disabled (`None`)
+    /// by default.
+    ///
+    /// Panics when `distance` is greater than 2.
+    pub fn set_auto_fuzzy(&mut self, distance: Option<u8>) {
+        assert!(
+            distance.map_or(true, |d| d <= MAX_FUZZINESS),
+            "distance must be at most 2"
+        );
+        for (_name, _boost, interpreter) in self.state.iter_mut() {
+            interpreter.auto_fuzzy = distance;
+        }
+    }
+
+    /// Configure the slop of phrases that don't specify one
+    ///
+    /// By default phrases only match their exact sequence of words,
+    /// which is equivalent to a slop of `0`.
+    pub fn set_default_slop(&mut self, slop: u32) {
+        for (_name, _boost, interpreter) in self.state.iter_mut() {
+            interpreter.default_slop = slop;
+        }
+    }
+
+    /// Limit how many distinct words a pattern like `choco*` may match
+    ///
+    /// Patterns that would match more words than this only search for
+    /// the ones that appear in the most documents. Defaults to 50.
+    ///
+    /// Panics when `max_expansions` is zero.
+    pub fn set_max_expansions(&mut self, max_expansions: usize) {
+        assert!(max_expansions > 0, "max_expansions must be positive");
+        for (_name, _boost, interpreter) in self.state.iter_mut() {
+            interpreter.max_expansions = max_expansions;
+        }
+    }
+
+    /// Also search for the synonyms of the words in a field
+    ///
+    /// Words and phrases with synonyms are expanded into a search
+    /// for either the original or any of its synonyms, combined the
+    /// same way a query over multiple fields is: `parse_dismax` only
+    /// scores the best match. Synonyms are analyzed like the field's
+    /// text, so with a stemming analyzer "green onions" is also a
+    /// synonym of "scallion". Multi-word synonyms are searched as
+    /// phrases and wildcard patterns are never expanded.
+    ///
+    /// Replaces any synonyms previously set for the field, `None`
+    /// disables expansion.
+    pub fn set_synonyms(&mut self, field: Field, synonyms: Option<&Synonyms>) {
+        if let Some((_name, _boost, interpreter)) = self
+            .position_by_field(field)
+            .and_then(|pos| self.state.get_mut(pos))
+        {
+            interpreter.set_synonyms(synonyms);
+        }
+    }
+
+    /// Configure the importance of synonyms compared to the words
+    /// actually present in the query
+    ///
+    /// Defaults to 0.5, so documents with the original words rank
+    /// higher than the ones with their synonyms.
+    ///
+    /// Panics when `boost` is not positive.
+    pub fn set_synonym_boost(&mut self, boost: f32) {
+        assert!(boost > 0.0, "boost must be positive");
+        for (_name, _boost, interpreter) in self.state.iter_mut() {
+            interpreter.synonym_boost = boost;
+        }
+    }
+
+    /// Allow querying a numeric field with comparisons like `name:<10`
+    ///
+    /// # Errors
+    ///
+    /// Will yield an error if `field` is not an indexed u64, i64 or f64
+    /// field in the index schema
+    pub fn add_range_field(&mut self, name: String, field: Field) -> Result<()> {
+        let entry = self.schema.get_field_entry(field);
+        let value_type = entry.field_type().value_type();
+
+        match value_type {
+            Type::U64 | Type::I64 | Type::F64 if entry.is_indexed() => {
+                self.ranges.retain(|(other, _field, _type)| other != &name);
+                self.ranges.push((name, field, value_type));
+                Ok(())
+            }
+            _ => Err(TantivyError::SchemaError(format!(
+                "Field {} is not an indexed numeric field",
+                entry.name()
+            ))),
+        }
+    }
+
     /// Configure which fields are queried by default
     ///
     /// When a query input doesn't specify a field name explicitly, the
@@ -116,20 +303,75 @@ impl QueryParser {
         self.default_indices = indices;
     }
 
+    /// Configure whether items without a `+` or `-` prefix are
+    /// required or optional
+    ///
+    /// The default, `Occur::Should`, makes a query like "bacon cheese"
+    /// find documents with either word. With `Occur::Must` it only
+    /// finds the ones with both, as if written as "+bacon +cheese".
+    /// Items joined with `OR` stay optional in either case.
+    ///
+    /// Panics when `occur` is `Occur::MustNot`.
+    pub fn set_default_occur(&mut self, occur: Occur) {
+        assert!(occur != Occur::MustNot, "occur must be Must or Should");
+        self.default_occur = occur;
+    }
+
+    /// Require documents to match a number of the optional items
+    ///
+    /// Only the items at the top level of a query count: the ones
+    /// within parentheses are combined as usual. Required items don't
+    /// count either, but when set at least one optional item must
+    /// match even if there are required ones.
+    ///
+    /// `None`, the default, only requires a single optional item to
+    /// match and only when there are no required ones.
+    pub fn set_minimum_should_match(&mut self, minimum: Option<MinimumShouldMatch>) {
+        self.minimum_should_match = minimum;
+    }
+
+    /// Bound how expensive the queries generated from any input get
+    ///
+    /// Inputs that go over any of the limits are truncated: only the
+    /// leading characters, clauses and phrase words that fit are
+    /// searched for. Use `check_limits` to reject them instead.
+    pub fn set_limits(&mut self, limits: QueryLimits) {
+        self.limits = limits;
+        for (_name, _boost, interpreter) in self.state.iter_mut() {
+            interpreter.max_phrase_length = limits.max_phrase_length;
+        }
+    }
+
+    /// Check whether parsing `input` would truncate it because of the
+    /// configured limits
+    ///
+    /// # Errors
+    ///
+    /// Yields the first limit `input` goes over, in the order it
+    /// appears
+    pub fn check_limits(&self, input: &str) -> std::result::Result<(), LimitExceeded> {
+        if let (_, Some(exceeded)) = self.limits.truncate_input(input) {
+            return Err(exceeded);
+        }
+
+        if let Ok((_, parsed)) = parse_query(input, self) {
+            let mut budget = Budget::new(self.limits);
+            self.fit_clauses(parsed, &mut budget);
+            if let Some(exceeded) = budget.exceeded.into_iter().next() {
+                return Err(exceeded);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Parse arbitrary user input into a tantivy query
     ///
     /// `None` may happen when the input is empty or the field analyzers end up
     /// emitting no tokens. Example: an analyzer that filters stop words would
     /// return `None` for a query like "the is at which".
     pub fn parse(&self, input: &str) -> Option<Box<dyn Query>> {
-        self.parse_inner(input, |queries| {
-            Box::new(BooleanQuery::from(
-                queries
-                    .into_iter()
-                    .map(|q| (Occur::Should, q))
-                    .collect::<Vec<_>>(),
-            ))
-        })
+        self.parse_inner(input, union, self.minimum_should_match)
     }
 
     /// Parse a query, taking multiple fields with similar vocabularies into
@@ -150,9 +392,182 @@ impl QueryParser {
             (0.0..=1.0).contains(&tiebreaker),
             "tiebreaker must be between 0 and 1.0"
         );
-        self.parse_inner(input, |queries| {
-            Box::new(DisMaxQuery::new(queries, tiebreaker))
-        })
+        self.parse_inner(
+            input,
+            |queries| Box::new(DisMaxQuery::new(queries, tiebreaker)),
+            self.minimum_should_match,
+        )
+    }
+
+    /// Parse a query with a different minimum of optional items to match
+    ///
+    /// Behaves like `QueryParser::parse_dixmax` when `tiebreaker` is
+    /// set and like `QueryParser::parse` otherwise, but ignores what
+    /// was configured via `set_minimum_should_match`.
+    ///
+    /// Panics when `tiebreaker` is lower than zero or greater than one.
+    pub fn parse_with_minimum_should_match(
+        &self,
+        input: &str,
+        minimum: Option<MinimumShouldMatch>,
+        tiebreaker: Option<f32>,
+    ) -> Option<Box<dyn Query>> {
+        if let Some(tiebreaker) = tiebreaker {
+            assert!(
+                (0.0..=1.0).contains(&tiebreaker),
+                "tiebreaker must be between 0 and 1.0"
+            );
+            self.parse_inner(
+                input,
+                |queries| Box::new(DisMaxQuery::new(queries, tiebreaker)),
+                minimum,
+            )
+        } else {
+            self.parse_inner(input, union, minimum)
+        }
+    }
+
+    /// Parse a query and explain what might be surprising about it
+    ///
+    /// Behaves like `QueryParser::parse`, but also reports on the parts
+    /// of the input that ended up being ignored or searched for in an
+    /// unexpected way, as well as words that can't be found in any
+    /// document visible to `searcher`. Useful for explaining why a
+    /// search found nothing.
+    ///
+    /// Going over the configured limits is reported once per limit.
+    pub fn parse_with_report(&self, input: &str, searcher: &Searcher) -> ParseReport {
+        let mut diagnostics = Vec::new();
+
+        let (input, exceeded) = self.limits.truncate_input(input);
+        let mut budget = Budget::new(self.limits);
+        budget.exceeded.extend(exceeded);
+
+        let parsed = match parse_query(input, self) {
+            Ok((remaining, parsed)) => {
+                if !remaining.trim().is_empty() {
+                    diagnostics.push(Diagnostic::IgnoredInput(remaining.to_owned()));
+                }
+                parsed
+            }
+            Err(_) => {
+                diagnostics.push(Diagnostic::IgnoredInput(input.to_owned()));
+                Vec::new()
+            }
+        };
+
+        let parsed = self.fit_clauses(parsed, &mut budget);
+
+        for exceeded in budget.exceeded {
+            let diagnostic = Diagnostic::LimitExceeded(exceeded);
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
+        }
+
+        self.diagnose(&parsed, searcher, &mut diagnostics);
+
+        let query = combine(self.clauses_from_raw(parsed, &union, self.minimum_should_match));
+
+        ParseReport { query, diagnostics }
+    }
+
+    fn diagnose(
+        &self,
+        parsed: &[RawClause],
+        searcher: &Searcher,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for raw_clause in parsed {
+            match raw_clause {
+                RawClause::Group(group) => self.diagnose(&group.clauses, searcher, diagnostics),
+                RawClause::Range(raw) => {
+                    if self.range_query(raw).is_none() {
+                        diagnostics.push(Diagnostic::InvalidRange {
+                            field_name: raw.field_name.to_owned(),
+                        });
+                    }
+                }
+                RawClause::Query(raw) => self.diagnose_query(raw, searcher, diagnostics),
+            }
+        }
+    }
+
+    fn diagnose_query(
+        &self,
+        raw_query: &RawQuery,
+        searcher: &Searcher,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if raw_query.field_name.is_none() && !raw_query.is_phrase {
+            if let Some(pos) = raw_query.input.find(FIELD_SEP) {
+                let (field_name, text) = (&raw_query.input[..pos], &raw_query.input[pos + 1..]);
+                if !field_name.is_empty() && !text.is_empty() {
+                    diagnostics.push(Diagnostic::UnknownField {
+                        field_name: field_name.to_owned(),
+                        input: raw_query.input.to_owned(),
+                    });
+                }
+            }
+        }
+
+        // Patterns aren't analyzed and fuzzy terms are expected to be
+        // missing from the index
+        if raw_query.is_wildcard || raw_query.fuzziness.is_some() {
+            return;
+        }
+
+        // Whether each token has been seen in any of the fields
+        let mut found: Vec<(String, bool)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+
+        for (_name, _boost, interpreter) in self
+            .indices_for(raw_query)
+            .into_iter()
+            .flat_map(|i| self.state.get(i))
+        {
+            for term in interpreter.terms(raw_query.input) {
+                let text = term.text().to_owned();
+                let in_index = searcher.doc_freq(&term) > 0;
+
+                if let Some(&pos) = positions.get(&text) {
+                    found[pos].1 |= in_index;
+                } else {
+                    positions.insert(text.clone(), found.len());
+                    found.push((text, in_index));
+                }
+            }
+        }
+
+        if found.is_empty() {
+            diagnostics.push(Diagnostic::NoTokens(raw_query.input.to_owned()));
+        }
+
+        for (text, in_index) in found {
+            if !in_index {
+                diagnostics.push(Diagnostic::UnknownTerm(text));
+            }
+        }
+    }
+
+    /// Parse a query into its syntax tree, without analyzing any text
+    ///
+    /// The result can be inspected, modified and turned back into
+    /// text (via `Display`) in a canonical form that `parse` and
+    /// `parse_ast` understand. Input that can't be parsed is dropped.
+    ///
+    /// The result records this parser's default occur, so items that
+    /// are optional despite `set_default_occur(Occur::Must)` render
+    /// joined with `OR` and the text means the same to any parser.
+    pub fn parse_ast(&self, input: &str) -> QueryAst {
+        let clauses = parse_query(input, self)
+            .map(|(_, parsed)| parsed.iter().map(Clause::from).collect())
+            .unwrap_or_default();
+
+        QueryAst {
+            clauses,
+            default_occur: self.default_occur.into(),
+        }
     }
 
     fn parse_inner<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
@@ -160,70 +575,169 @@ impl QueryParser {
         input: &str,
         // Guaranteed to receive a vec of len > 1 if called
         many_handler: F,
+        minimum: Option<MinimumShouldMatch>,
     ) -> Option<Box<dyn Query>> {
+        let (input, _exceeded) = self.limits.truncate_input(input);
         let (_, parsed) = parse_query(input, self).ok()?;
-        let mut clauses = Vec::new();
-        let mut num_must_not = 0;
+        let parsed = self.fit_clauses(parsed, &mut Budget::new(self.limits));
+        combine(self.clauses_from_raw(parsed, &many_handler, minimum))
+    }
 
-        parsed
-            .into_iter()
-            .map(|raw| (self.queries_from_raw(&raw), raw))
-            .filter(|(queries, _)| !queries.is_empty())
-            .for_each(|(queries, raw)| {
-                if raw.occur == Occur::MustNot {
-                    for query in queries {
-                        num_must_not += 1;
-                        clauses.push((Occur::MustNot, query));
+    /// Keeps the leading clauses that fit within `budget`, dropping
+    /// every one after the first that doesn't
+    fn fit_clauses<'a>(
+        &self,
+        parsed: Vec<RawClause<'a>>,
+        budget: &mut Budget,
+    ) -> Vec<RawClause<'a>> {
+        let mut fitting = Vec::with_capacity(parsed.len());
+
+        for raw_clause in parsed {
+            if budget.exhausted {
+                break;
+            }
+
+            match raw_clause {
+                RawClause::Group(mut group) => {
+                    group.clauses = self.fit_clauses(group.clauses, budget);
+                    if !group.clauses.is_empty() {
+                        fitting.push(RawClause::Group(group));
                     }
-                } else if queries.len() == 1 {
-                    clauses.push((raw.occur, queries.into_iter().next().unwrap()));
-                } else {
-                    // Now we have multiple positive queries that were generated
-                    // out of a single raw query.
-                    clauses.push((raw.occur, many_handler(queries)));
                 }
-            });
-
-        match clauses.len() {
-            0 => None,
-            1 => {
-                let (occur, query) = clauses.into_iter().next().unwrap();
-                if occur == Occur::MustNot {
-                    Some(Box::new(BooleanQuery::from(vec![
-                        (Occur::MustNot, query),
-                        (Occur::Must, Box::new(AllQuery)),
-                    ])))
-                } else {
-                    Some(query)
+                RawClause::Range(raw) => {
+                    if budget.take(0) {
+                        fitting.push(RawClause::Range(raw));
+                    }
+                }
+                RawClause::Query(raw) => {
+                    let mut cost = 0;
+                    for (_name, _boost, interpreter) in self
+                        .indices_for(&raw)
+                        .into_iter()
+                        .flat_map(|i| self.state.get(i))
+                    {
+                        let (field_cost, phrase_length) = interpreter.cost(&raw);
+                        cost += field_cost;
+                        if let Some(length) = phrase_length {
+                            budget.check_phrase(length);
+                        }
+                    }
+
+                    if budget.take(cost) {
+                        fitting.push(RawClause::Query(raw));
+                    }
                 }
             }
-            num_clauses => {
-                if num_clauses == num_must_not {
-                    clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        fitting
+    }
+
+    fn clauses_from_raw<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        parsed: Vec<RawClause>,
+        many_handler: &F,
+        minimum: Option<MinimumShouldMatch>,
+    ) -> Vec<(Occur, Box<dyn Query>)> {
+        let mut clauses = Vec::new();
+        let mut filters = Vec::new();
+
+        for raw_clause in parsed {
+            match raw_clause {
+                RawClause::Group(group) => {
+                    if let Some(query) =
+                        combine(self.clauses_from_raw(group.clauses, many_handler, None))
+                    {
+                        clauses.push((group.occur, query));
+                    }
+                }
+                RawClause::Range(raw) => {
+                    if let Some(query) = self.range_query(&raw) {
+                        // Comparisons are filters: only `-` changes that
+                        let occur = if raw.occur == Occur::MustNot {
+                            Occur::MustNot
+                        } else {
+                            Occur::Must
+                        };
+                        filters.push((occur, query));
+                    }
                 }
+                RawClause::Query(raw) => {
+                    let queries = self.queries_from_raw(&raw, many_handler);
 
-                Some(Box::new(BooleanQuery::from(clauses)))
+                    if queries.is_empty() {
+                        continue;
+                    } else if raw.occur == Occur::MustNot {
+                        for query in queries {
+                            clauses.push((Occur::MustNot, query));
+                        }
+                    } else if queries.len() == 1 {
+                        clauses.push((raw.occur, queries.into_iter().next().unwrap()));
+                    } else {
+                        // Now we have multiple positive queries that were generated
+                        // out of a single raw query.
+                        clauses.push((raw.occur, many_handler(queries)));
+                    }
+                }
             }
         }
+
+        if let Some(minimum) = minimum {
+            clauses = require_minimum(clauses, minimum);
+        }
+
+        if filters.is_empty() {
+            return clauses;
+        }
+
+        // Mixing required filters with optional clauses would make the
+        // latter not required to match at all
+        let mut combined = Vec::with_capacity(filters.len() + 1);
+        if let Some(query) = combine(clauses) {
+            combined.push((Occur::Must, query));
+        }
+        combined.extend(filters);
+
+        combined
     }
 
-    fn queries_from_raw(&self, raw_query: &RawQuery) -> Vec<Box<dyn Query>> {
-        let indices = if let Some(position) = raw_query
+    /// Positions of the fields a raw query should search
+    fn indices_for(&self, raw_query: &RawQuery) -> Vec<usize> {
+        if let Some(position) = raw_query
             .field_name
             .and_then(|field_name| self.position_by_name(field_name))
         {
             vec![position]
         } else {
             self.default_indices.clone()
-        };
+        }
+    }
 
-        indices
+    fn queries_from_raw<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        raw_query: &RawQuery,
+        many_handler: &F,
+    ) -> Vec<Box<dyn Query>> {
+        self.indices_for(raw_query)
             .into_iter()
             .flat_map(|i| self.state.get(i))
-            .flat_map(|(_, boost, interpreter)| {
+            .flat_map(|(_, field_boost, interpreter)| {
+                let boost = match (field_boost, raw_query.boost) {
+                    (Some(field_boost), Some(boost)) => Some(field_boost * boost),
+                    (field_boost, boost) => field_boost.or(boost),
+                };
+
                 interpreter.to_query(raw_query).map(|query| {
+                    let mut synonyms = interpreter.synonym_queries(raw_query);
+                    let query = if synonyms.is_empty() {
+                        query
+                    } else {
+                        synonyms.insert(0, query);
+                        many_handler(synonyms)
+                    };
+
                     if let Some(val) = boost {
-                        Box::new(BoostQuery::new(query, *val))
+                        Box::new(BoostedQuery::new(query, val))
                     } else {
                         query
                     }
@@ -232,6 +746,30 @@ impl QueryParser {
             .collect()
     }
 
+    /// `None` when the bounds don't fit the field type, like
+    /// `calories:<1.5` for an integer field
+    fn range_query(&self, raw: &RawRange) -> Option<Box<dyn Query>> {
+        let (_name, field, value_type) = self
+            .ranges
+            .iter()
+            .find(|(name, _field, _type)| name == raw.field_name)?;
+
+        let query = match value_type {
+            Type::U64 => {
+                RangeQuery::new_u64_bounds(*field, parse_bound(raw.lower)?, parse_bound(raw.upper)?)
+            }
+            Type::I64 => {
+                RangeQuery::new_i64_bounds(*field, parse_bound(raw.lower)?, parse_bound(raw.upper)?)
+            }
+            Type::F64 => {
+                RangeQuery::new_f64_bounds(*field, parse_bound(raw.lower)?, parse_bound(raw.upper)?)
+            }
+            _ => unreachable!("add_range_field only accepts numeric fields"),
+        };
+
+        Some(Box::new(query))
+    }
+
     fn position_by_name(&self, field_name: &str) -> Option<usize> {
         self.state
             .iter()
@@ -255,67 +793,297 @@ impl FieldNameValidator for QueryParser {
                 opt_name.as_ref().map_or(false, |name| name == field_name)
             })
     }
+
+    fn check_range(&self, field_name: &str) -> bool {
+        self.ranges
+            .iter()
+            .any(|(name, _field, _type)| name == field_name)
+    }
+
+    fn default_occur(&self) -> Occur {
+        self.default_occur
+    }
 }
 
-struct Interpreter {
-    field: Field,
-    analyzer: TextAnalyzer,
+fn parse_bound<T: FromStr>(bound: Bound<&str>) -> Option<Bound<T>> {
+    Some(match bound {
+        Bound::Included(value) => Bound::Included(value.parse().ok()?),
+        Bound::Excluded(value) => Bound::Excluded(value.parse().ok()?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
 }
 
-impl Interpreter {
-    fn to_query(&self, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
-        let mut terms = Vec::new();
-        let mut stream = self.analyzer.token_stream(raw_query.input);
+/// Combines queries generated out of a single raw query, as `parse` does
+fn union(queries: Vec<Box<dyn Query>>) -> Box<dyn Query> {
+    Box::new(BooleanQuery::from(
+        queries
+            .into_iter()
+            .map(|q| (Occur::Should, q))
+            .collect::<Vec<_>>(),
+    ))
+}
 
-        stream.process(&mut |token| {
-            terms.push(Term::from_field_text(self.field, &token.text));
-        });
+/// Replaces the optional clauses with a single required one that only
+/// matches when enough of them do. Scores are summed like a boolean
+/// query would
+fn require_minimum(
+    clauses: Vec<(Occur, Box<dyn Query>)>,
+    minimum: MinimumShouldMatch,
+) -> Vec<(Occur, Box<dyn Query>)> {
+    let (optional, mut required): (Vec<_>, Vec<_>) = clauses
+        .into_iter()
+        .partition(|(occur, _query)| *occur == Occur::Should);
 
-        if terms.is_empty() {
-            return None;
+    let mut queries = optional
+        .into_iter()
+        .map(|(_occur, query)| query)
+        .collect::<Vec<_>>();
+
+    match queries.len() {
+        0 => {}
+        1 => required.push((Occur::Must, queries.pop().unwrap())),
+        _ => {
+            let mut query = DisMaxQuery::new(queries, 1.0);
+            query.set_minimum_should_match(Some(minimum));
+            required.push((Occur::Must, Box::new(query)));
         }
+    }
 
-        let query: Box<dyn Query> = if terms.len() == 1 {
-            Box::new(TermQuery::new(
-                terms.pop().unwrap(),
-                IndexRecordOption::WithFreqs,
-            ))
-        } else if raw_query.is_phrase {
-            Box::new(PhraseQuery::new(terms))
-        } else {
-            // An analyzer might emit multiple tokens even if the
-            // raw parser only got one (say: raw takes "word", but
-            // analyzer is actually a char tokenizer)
-            Box::new(BooleanQuery::new_multiterms_query(terms))
-        };
+    required
+}
 
-        Some(query)
+/// Turns a list of clauses into a single query
+fn combine(mut clauses: Vec<(Occur, Box<dyn Query>)>) -> Option<Box<dyn Query>> {
+    match clauses.len() {
+        0 => None,
+        1 => {
+            let (occur, query) = clauses.pop().unwrap();
+            if occur == Occur::MustNot {
+                Some(Box::new(BooleanQuery::from(vec![
+                    (Occur::MustNot, query),
+                    (Occur::Must, Box::new(AllQuery)),
+                ])))
+            } else {
+                Some(query)
+            }
+        }
+        _ => {
+            // A purely negative query would match nothing
+            if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+                clauses.push((Occur::Must, Box::new(AllQuery)));
+            }
+
+            Some(Box::new(BooleanQuery::from(clauses)))
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+const DEFAULT_MAX_EXPANSIONS: usize = 50;
+const DEFAULT_SYNONYM_BOOST: f32 = 0.5;
 
-    use tantivy::tokenizer::TokenizerManager;
+struct Interpreter {
+    field: Field,
+    analyzer: TextAnalyzer,
+    auto_fuzzy: Option<u8>,
+    max_expansions: usize,
+    default_slop: u32,
+    /// The analyzed alternatives of every analyzed word or phrase
+    synonyms: HashMap<Vec<Term>, Vec<Vec<Term>>>,
+    synonym_boost: f32,
+    max_phrase_length: Option<usize>,
+}
 
-    fn test_interpreter() -> Interpreter {
-        Interpreter {
-            field: Field::from_field_id(0),
-            analyzer: TokenizerManager::default().get("en_stem").unwrap(),
+impl Interpreter {
+    fn new(field: Field, analyzer: TextAnalyzer) -> Self {
+        Self {
+            field,
+            analyzer,
+            auto_fuzzy: None,
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+            default_slop: 0,
+            synonyms: HashMap::new(),
+            synonym_boost: DEFAULT_SYNONYM_BOOST,
+            max_phrase_length: None,
         }
     }
 
-    #[test]
-    fn empty_raw_is_none() {
-        assert!(test_interpreter().to_query(&RawQuery::new("")).is_none());
-    }
+    fn set_synonyms(&mut self, synonyms: Option<&Synonyms>) {
+        let mut analyzed: HashMap<Vec<Term>, Vec<Vec<Term>>> = HashMap::new();
 
-    #[test]
-    fn simple_raw_is_termquery() {
-        let query = test_interpreter()
-            .to_query(&RawQuery::new("word"))
-            .expect("parses to a Some(Query)");
+        for (word, alternatives) in synonyms.into_iter().flat_map(Synonyms::entries) {
+            let key = self.terms(word);
+            if key.is_empty() {
+                continue;
+            }
+
+            let expansions = analyzed.entry(key.clone()).or_insert_with(Vec::new);
+            for alternative in alternatives {
+                let terms = self.terms(alternative);
+                if !terms.is_empty() && terms != key && !expansions.contains(&terms) {
+                    expansions.push(terms);
+                }
+            }
+        }
+
+        analyzed.retain(|_key, expansions| !expansions.is_empty());
+        self.synonyms = analyzed;
+    }
+
+    /// Queries for every synonym of `raw_query`, already boosted
+    fn synonym_queries(&self, raw_query: &RawQuery) -> Vec<Box<dyn Query>> {
+        if raw_query.is_wildcard || self.synonyms.is_empty() {
+            return Vec::new();
+        }
+
+        let expansions = match self.synonyms.get(&self.terms(raw_query.input)) {
+            Some(expansions) => expansions,
+            None => return Vec::new(),
+        };
+
+        expansions
+            .iter()
+            .map(|terms| {
+                let query: Box<dyn Query> = if terms.len() == 1 {
+                    Box::new(TermQuery::new(
+                        terms[0].clone(),
+                        IndexRecordOption::WithFreqs,
+                    ))
+                } else {
+                    self.phrase_query(terms.clone(), raw_query.slop)
+                };
+                Box::new(BoostedQuery::new(query, self.synonym_boost)) as Box<dyn Query>
+            })
+            .collect()
+    }
+
+    fn phrase_query(&self, terms: Vec<Term>, slop: Option<u32>) -> Box<dyn Query> {
+        let slop = slop.unwrap_or(self.default_slop);
+        if slop == 0 {
+            Box::new(PhraseQuery::new(terms))
+        } else {
+            Box::new(SloppyPhraseQuery::new(terms, slop))
+        }
+    }
+
+    fn to_query(&self, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
+        if raw_query.is_wildcard {
+            // Running the analyzer would split the pattern apart
+            return Some(Box::new(WildcardQuery::new(
+                self.field,
+                raw_query.input.to_lowercase(),
+                self.max_expansions,
+            )));
+        }
+
+        let mut terms = self.terms(raw_query.input);
+        if terms.is_empty() {
+            return None;
+        }
+
+        let query: Box<dyn Query> = if raw_query.is_phrase {
+            if let Some(max) = self.max_phrase_length {
+                terms.truncate(max.max(1));
+            }
+
+            if terms.len() == 1 {
+                Box::new(TermQuery::new(
+                    terms.pop().unwrap(),
+                    IndexRecordOption::WithFreqs,
+                ))
+            } else {
+                self.phrase_query(terms, raw_query.slop)
+            }
+        } else if terms.len() == 1 {
+            self.term_query(terms.pop().unwrap(), raw_query.fuzziness)
+        } else if raw_query.fuzziness.is_some() || self.auto_fuzzy.is_some() {
+            Box::new(BooleanQuery::from(
+                terms
+                    .into_iter()
+                    .map(|term| (Occur::Should, self.term_query(term, raw_query.fuzziness)))
+                    .collect::<Vec<_>>(),
+            ))
+        } else {
+            // An analyzer might emit multiple tokens even if the
+            // raw parser only got one (say: raw takes "word", but
+            // analyzer is actually a char tokenizer)
+            Box::new(BooleanQuery::new_multiterms_query(terms))
+        };
+
+        Some(query)
+    }
+
+    /// How many terms the queries for `raw_query` search for, along
+    /// with the number of words when it's a phrase
+    fn cost(&self, raw_query: &RawQuery) -> (usize, Option<usize>) {
+        if raw_query.is_wildcard {
+            return (1, None);
+        }
+
+        let terms = self.terms(raw_query.input);
+        let synonyms = if self.synonyms.is_empty() {
+            0
+        } else {
+            self.synonyms
+                .get(&terms)
+                .map_or(0, |expansions| expansions.iter().map(Vec::len).sum())
+        };
+
+        if raw_query.is_phrase {
+            let length = terms.len();
+            let searched = self
+                .max_phrase_length
+                .map_or(length, |max| length.min(max.max(1)));
+            (searched + synonyms, Some(length))
+        } else {
+            (terms.len() + synonyms, None)
+        }
+    }
+
+    fn terms(&self, input: &str) -> Vec<Term> {
+        let mut terms = Vec::new();
+        let mut stream = self.analyzer.token_stream(input);
+
+        stream.process(&mut |token| {
+            terms.push(Term::from_field_text(self.field, &token.text));
+        });
+
+        terms
+    }
+
+    fn term_query(&self, term: Term, fuzziness: Option<u8>) -> Box<dyn Query> {
+        if let Some(distance) = fuzziness {
+            Box::new(FuzzyTermQuery::new(term, distance, true))
+        } else if let Some(distance) = self.auto_fuzzy {
+            Box::new(AutoFuzzyQuery::new(term, distance))
+        } else {
+            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::tokenizer::TokenizerManager;
+
+    fn test_interpreter() -> Interpreter {
+        Interpreter::new(
+            Field::from_field_id(0),
+            TokenizerManager::default().get("en_stem").unwrap(),
+        )
+    }
+
+    #[test]
+    fn empty_raw_is_none() {
+        assert!(test_interpreter().to_query(&RawQuery::new("")).is_none());
+    }
+
+    #[test]
+    fn simple_raw_is_termquery() {
+        let query = test_interpreter()
+            .to_query(&RawQuery::new("word"))
+            .expect("parses to a Some(Query)");
 
         assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
     }
@@ -338,17 +1106,33 @@ mod tests {
         assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
     }
 
+    #[test]
+    fn fuzzy_raw_is_fuzzytermquery() {
+        let query = test_interpreter()
+            .to_query(&RawQuery::new("parmesean").fuzzy(1))
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<FuzzyTermQuery>().is_some());
+    }
+
+    #[test]
+    fn wildcard_raw_is_wildcardquery() {
+        let query = test_interpreter()
+            .to_query(&RawQuery::new("Choco*").wildcard())
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<WildcardQuery>().is_some());
+    }
+
     fn single_field_test_parser() -> QueryParser {
         QueryParser {
             default_indices: vec![0],
-            state: vec![(
-                None,
-                None,
-                Interpreter {
-                    field: Field::from_field_id(0),
-                    analyzer: TokenizerManager::default().get("en_stem").unwrap(),
-                },
-            )],
+            state: vec![(None, None, test_interpreter())],
+            ranges: Vec::new(),
+            schema: SchemaBuilder::new().build(),
+            minimum_should_match: None,
+            default_occur: Occur::Should,
+            limits: QueryLimits::default(),
         }
     }
 
@@ -358,9 +1142,9 @@ mod tests {
     }
 
     use tantivy::{
-        collector::TopDocs,
+        collector::{Count, TopDocs},
         doc,
-        schema::{SchemaBuilder, TEXT},
+        schema::{SchemaBuilder, INDEXED, STORED, TEXT},
         DocAddress,
     };
 
@@ -428,6 +1212,590 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn grouping_and_operators() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "bacon and egg"));
+        writer.add_document(doc!(body => "pancetta carbonara"));
+        writer.add_document(doc!(body => "bacon sandwich"));
+        writer.add_document(doc!(body => "egg salad"));
+        writer.commit()?;
+
+        let parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(4))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(vec![1, 2], search("(bacon OR pancetta) AND -egg"));
+        assert_eq!(vec![1, 2], search("(bacon pancetta) NOT egg"));
+        assert_eq!(vec![0], search("bacon AND egg"));
+        assert_eq!(vec![0, 2], search("+bacon (egg OR sandwich)"));
+        assert_eq!(vec![1], search("-(bacon egg)"));
+        assert_eq!(vec![1, 2, 3], search("-(bacon AND egg)"));
+        // Unbalanced input still finds things
+        assert_eq!(vec![0, 1, 2], search("(bacon OR pancetta"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fuzzy_matching() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "parmesan risotto"));
+        writer.add_document(doc!(body => "grilled zucchini"));
+        writer.add_document(doc!(body => "seafood risottos"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher.search(&query, &Count).expect("working index")
+        };
+
+        assert_eq!(0, count(&parser, "parmesean"));
+        assert_eq!(1, count(&parser, "parmesean~"));
+        assert_eq!(0, count(&parser, "zuchinni~"));
+        assert_eq!(1, count(&parser, "zuchinni~2"));
+        assert_eq!(2, count(&parser, "parmesean~ zuchini~"));
+
+        parser.set_auto_fuzzy(Some(1));
+        assert_eq!(1, count(&parser, "parmesean"));
+        assert_eq!(0, count(&parser, "zuchinni"));
+        // Only terms that aren't in the index become fuzzy
+        assert_eq!(1, count(&parser, "risotto"));
+        assert_eq!(2, count(&parser, "risotto~"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard_matching() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "chocolate cake"));
+        writer.add_document(doc!(body => "chocolatey brownies"));
+        writer.add_document(doc!(body => "chocolate chip cookies"));
+        writer.add_document(doc!(body => "chorizo stew"));
+        writer.commit()?;
+
+        // A second segment, so that expansions get merged
+        writer.add_document(doc!(body => "choc chip muffins"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher.search(&query, &Count).expect("working index")
+        };
+
+        assert_eq!(3, count(&parser, "choco*"));
+        assert_eq!(3, count(&parser, "CHOCO*"));
+        assert_eq!(5, count(&parser, "ch*"));
+        assert_eq!(2, count(&parser, "choc*late"));
+        assert_eq!(0, count(&parser, "vanilla*"));
+        assert_eq!(1, count(&parser, "ch* -choco* -chip"));
+
+        // Only "chocolate" (2 docs) survives over "chocolatey" (1 doc)
+        parser.set_max_expansions(1);
+        assert_eq!(2, count(&parser, "choco*"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn range_clauses() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let calories = builder.add_u64_field("calories_field", INDEXED);
+        let rating = builder.add_f64_field("rating", INDEXED);
+        let unindexed = builder.add_u64_field("unindexed", STORED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "pasta salad", calories => 300u64, rating => 4.5));
+        writer.add_document(doc!(body => "pasta bake", calories => 800u64, rating => 3.0));
+        writer.add_document(doc!(body => "green salad", calories => 150u64, rating => 4.0));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        assert!(parser.add_range_field("body".to_owned(), body).is_err());
+        assert!(parser
+            .add_range_field("unindexed".to_owned(), unindexed)
+            .is_err());
+        parser.add_range_field("calories".to_owned(), calories)?;
+        parser.add_range_field("rating".to_owned(), rating)?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher.search(&query, &Count).expect("working index")
+        };
+
+        assert_eq!(1, count("pasta calories:<400"));
+        assert_eq!(2, count("calories:150..800"));
+        assert_eq!(3, count("calories:150..=800"));
+        assert_eq!(2, count("calories:>=300"));
+        assert_eq!(1, count("calories:>300"));
+        assert_eq!(2, count("rating:>3.5"));
+        assert_eq!(1, count("salad AND -rating:..4.5"));
+        assert_eq!(1, count("salad calories:<200"));
+        // Comparisons are required even when next to OR
+        assert_eq!(0, count("salad OR calories:>=800"));
+        assert_eq!(3, count("-calories:<1"));
+        // Bounds that don't fit the field type are ignored
+        assert_eq!(2, count("pasta calories:<1.5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn term_boosting() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "bacon cheese"));
+        writer.add_document(doc!(body => "deep fry cheese"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let top = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &TopDocs::with_limit(1))
+                .expect("working index")[0]
+        };
+
+        let (score, _addr) = top(&parser, "bacon");
+        let (boosted, _addr) = top(&parser, "bacon^2");
+        assert!((score * 2.0 - boosted).abs() < 1e-5);
+
+        // Composes with the field boost
+        parser.set_boost(body, Some(1.5));
+        let (boosted, _addr) = top(&parser, "bacon^2");
+        assert!((score * 3.0 - boosted).abs() < 1e-5);
+
+        assert_eq!(DocAddress(0, 1), top(&parser, "bacon \"deep fry\"^2").1);
+        assert_eq!(DocAddress(0, 0), top(&parser, "bacon \"deep fry\"^0.1").1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sloppy_raw_phrase_is_sloppyphrasequery() {
+        let interpreter = test_interpreter();
+
+        let query = interpreter
+            .to_query(&RawQuery::new("chicken thighs").phrase().slop(2))
+            .expect("parses to a Some(Query)");
+        assert!(query.as_any().downcast_ref::<SloppyPhraseQuery>().is_some());
+
+        let query = interpreter
+            .to_query(&RawQuery::new("chicken thighs").phrase().slop(0))
+            .expect("parses to a Some(Query)");
+        assert!(query.as_any().downcast_ref::<PhraseQuery>().is_some());
+    }
+
+    #[test]
+    fn phrase_slop() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "roast chicken thighs"));
+        writer.add_document(doc!(body => "chicken and thighs"));
+        writer.add_document(doc!(body => "thighs of chicken"));
+        writer.add_document(doc!(body => "chicken wings, rice, beans and beef thighs"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(4))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(vec![0], search(&parser, "\"chicken thighs\""));
+        assert_eq!(vec![0, 1], search(&parser, "\"chicken thighs\"~1"));
+        assert_eq!(vec![0, 1], search(&parser, "\"chicken thighs\"~2"));
+        assert_eq!(vec![0, 1, 2], search(&parser, "\"chicken thighs\"~3"));
+        assert_eq!(vec![0, 1, 2, 3], search(&parser, "\"chicken thighs\"~5"));
+
+        parser.set_default_slop(1);
+        assert_eq!(vec![0, 1], search(&parser, "\"chicken thighs\""));
+        assert_eq!(vec![0], search(&parser, "\"chicken thighs\"~0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn synonym_expansion() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "cilantro", body => "scallion"));
+        writer.add_document(doc!(title => "coriander", body => "green onion"));
+        writer.add_document(doc!(title => "aubergine", body => "eggplant"));
+        writer.commit()?;
+
+        let mut synonyms = Synonyms::new();
+        synonyms.add_equivalent(&["cilantro", "coriander"]);
+        synonyms.add_equivalent(&["scallion", "green onion"]);
+        synonyms.add_mapping(&["aubergine"], &["eggplant"]);
+
+        let mut parser = QueryParser::new(&index, vec![title, body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![0], search(&parser, "cilantro"));
+
+        parser.set_synonyms(title, Some(&synonyms));
+        parser.set_synonyms(body, Some(&synonyms));
+
+        // The original word ranks first
+        assert_eq!(vec![0, 1], search(&parser, "cilantro"));
+        assert_eq!(vec![1, 0], search(&parser, "coriander"));
+        // Multi-word synonyms are phrases and matched after analysis
+        assert_eq!(vec![0, 1], search(&parser, "Scallion"));
+        assert_eq!(vec![1, 0], search(&parser, "\"Green Onion\""));
+        assert_eq!(vec![1], search(&parser, "green"));
+        // Mappings go one way
+        assert_eq!(vec![2], search(&parser, "title:aubergine"));
+        assert_eq!(Vec::<u32>::new(), search(&parser, "title:eggplant"));
+        // Prohibiting a word also prohibits its synonyms
+        assert_eq!(vec![2], search(&parser, "-cilantro"));
+        // Per field
+        parser.set_synonyms(title, None);
+        assert_eq!(vec![0], search(&parser, "cilantro"));
+        assert!(parser.parse_dixmax("body:scallion", 0.1).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn minimum_should_match() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let calories = builder.add_u64_field("calories", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(
+            doc!(title => "lemon chicken", body => "garlic thyme", calories => 500u64),
+        );
+        writer.add_document(doc!(title => "roast chicken", body => "potato", calories => 700u64));
+        writer.add_document(doc!(title => "mashed potato", body => "butter", calories => 300u64));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![title, body])?;
+        parser.add_range_field("calories".to_owned(), calories)?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |query: Option<Box<dyn Query>>| {
+            let mut found = searcher
+                .search(&query.expect("yields Some()"), &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        let input = "chicken garlic lemon thyme potato";
+        assert_eq!(vec![0, 1, 2], search(parser.parse(input)));
+
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Absolute(2)));
+        assert_eq!(vec![0, 1], search(parser.parse(input)));
+        assert_eq!(vec![0, 1], search(parser.parse_dixmax(input, 0.1)));
+
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Percentage(60)));
+        assert_eq!(vec![0], search(parser.parse(input)));
+        // Rounded down
+        assert_eq!(vec![0, 1, 2], search(parser.parse("chicken potato")));
+        // Required items don't count, ranges are still filters
+        assert_eq!(vec![0, 1], search(parser.parse("+chicken lemon potato")));
+        assert_eq!(
+            vec![1],
+            search(parser.parse("potato chicken calories:>600"))
+        );
+        // Capped at the number of optional items
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Absolute(3)));
+        assert_eq!(vec![1], search(parser.parse("chicken potato")));
+        // Groups count as a single item
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Absolute(2)));
+        assert_eq!(vec![0, 1], search(parser.parse("chicken potato garlic")));
+        assert_eq!(vec![0], search(parser.parse("(chicken potato) garlic")));
+
+        let overridden = parser.parse_with_minimum_should_match(input, None, Some(0.1));
+        assert_eq!(vec![0, 1, 2], search(overridden));
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_occur() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(body => "bacon cheese"));
+        writer.add_document(doc!(body => "bacon eggs"));
+        writer.add_document(doc!(body => "cheese eggs"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(vec![0, 1, 2], search(&parser, "bacon cheese"));
+
+        parser.set_default_occur(Occur::Must);
+        assert_eq!(vec![0], search(&parser, "bacon cheese"));
+        assert_eq!(vec![1], search(&parser, "bacon -cheese"));
+        assert_eq!(vec![0, 1, 2], search(&parser, "bacon OR cheese"));
+        assert_eq!(vec![1, 2], search(&parser, "eggs (bacon OR cheese)"));
+        assert_eq!("+bacon +eggs", parser.parse_ast("bacon eggs").to_string());
+
+        let mut should_parser = QueryParser::new(&index, vec![body])?;
+        should_parser.set_default_occur(Occur::Should);
+        for (input, canonical) in &[
+            ("bacon OR eggs", "bacon OR eggs"),
+            ("bacon OR eggs cheese", "bacon OR eggs OR +cheese"),
+            ("bacon -eggs OR cheese", "+bacon -eggs OR cheese"),
+            ("cheese (bacon OR eggs)", "+cheese +(bacon OR eggs)"),
+            ("bacon OR", "+bacon +OR"),
+        ] {
+            let ast = parser.parse_ast(input);
+            assert_eq!(*canonical, ast.to_string());
+            assert_eq!(ast, parser.parse_ast(canonical));
+            // And it means the same regardless of the default
+            assert_eq!(ast.clauses, should_parser.parse_ast(canonical).clauses);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_limits() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "bacon", body => "crispy bacon strips"));
+        writer.add_document(doc!(title => "cheese", body => "melted cheese on toast"));
+        writer.add_document(doc!(title => "eggs", body => "scrambled eggs on toast"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![title, body])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(3))
+                .expect("working index")
+                .into_iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>();
+            found.sort();
+            found
+        };
+
+        assert_eq!(Ok(()), parser.check_limits("bacon cheese eggs"));
+
+        parser.set_limits(QueryLimits {
+            max_clauses: Some(2),
+            ..QueryLimits::default()
+        });
+        assert_eq!(vec![0, 1], search(&parser, "bacon cheese eggs"));
+        assert_eq!(vec![0, 1], search(&parser, "bacon (cheese eggs)"));
+        assert_eq!(
+            Err(LimitExceeded::Clauses(2)),
+            parser.check_limits("bacon (cheese eggs)")
+        );
+        assert_eq!(Ok(()), parser.check_limits("bacon cheese"));
+
+        // Each word is searched in both fields
+        parser.set_limits(QueryLimits {
+            max_term_queries: Some(4),
+            ..QueryLimits::default()
+        });
+        assert_eq!(vec![0, 1], search(&parser, "bacon cheese eggs"));
+        // Everything after the first item that doesn't fit is dropped
+        assert_eq!(vec![0], search(&parser, "title:bacon \"on toast\" eggs"));
+        assert_eq!(
+            Err(LimitExceeded::TermQueries(4)),
+            parser.check_limits("bacon \"on toast\"")
+        );
+
+        // Phrases are shortened instead of dropped
+        parser.set_limits(QueryLimits {
+            max_phrase_length: Some(2),
+            ..QueryLimits::default()
+        });
+        assert_eq!(vec![1], search(&parser, "\"melted cheese sandwich\""));
+        assert_eq!(
+            Err(LimitExceeded::PhraseLength(2)),
+            parser.check_limits("\"melted cheese sandwich\"")
+        );
+
+        parser.set_limits(QueryLimits {
+            max_input_length: Some(8),
+            ..QueryLimits::default()
+        });
+        assert_eq!(vec![0], search(&parser, "bacon eggs"));
+        assert_eq!(
+            Err(LimitExceeded::InputLength(8)),
+            parser.check_limits("bacon eggs")
+        );
+
+        let report = parser.parse_with_report("bacon eggs", &searcher);
+        assert_eq!(
+            vec![
+                Diagnostic::LimitExceeded(LimitExceeded::InputLength(8)),
+                Diagnostic::UnknownTerm("eg".to_owned())
+            ],
+            report.diagnostics
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_report() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let body = builder.add_text_field("body", TEXT);
+        let calories = builder.add_u64_field("calories", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(title => "Bacon", body => "crispy bacon", calories => 300u64));
+        writer.add_document(doc!(title => "Cheese", body => "aged cheese", calories => 400u64));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![title, body])?;
+        parser.add_range_field("calories".to_owned(), calories)?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let report = parser.parse_with_report("bacon cheese", &searcher);
+        assert!(report.query.is_some());
+        assert!(report.diagnostics.is_empty());
+
+        let report = parser.parse_with_report(
+            "crispy tofu author:joe , (calories:<1.5) \"tofu bacon\" choco* brocoli~",
+            &searcher,
+        );
+        assert!(report.query.is_some());
+        assert_eq!(
+            vec![
+                Diagnostic::UnknownTerm("tofu".to_owned()),
+                Diagnostic::UnknownField {
+                    field_name: "author".to_owned(),
+                    input: "author:joe".to_owned()
+                },
+                Diagnostic::UnknownTerm("author".to_owned()),
+                Diagnostic::UnknownTerm("joe".to_owned()),
+                Diagnostic::NoTokens(",".to_owned()),
+                Diagnostic::InvalidRange {
+                    field_name: "calories".to_owned()
+                },
+                Diagnostic::UnknownTerm("tofu".to_owned()),
+            ],
+            report.diagnostics
+        );
+
+        // Only searched in "title", where it doesn't appear
+        let report = parser.parse_with_report("title:aged", &searcher);
+        assert_eq!(
+            vec![Diagnostic::UnknownTerm("aged".to_owned())],
+            report.diagnostics
+        );
+
+        let report = parser.parse_with_report("   ", &searcher);
+        assert!(report.query.is_none());
+        assert!(report.diagnostics.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn field_boosting() -> Result<()> {
         let mut builder = SchemaBuilder::new();
@@ -0,0 +1,362 @@
+use tantivy::{
+    postings::SegmentPostings,
+    query::{BooleanQuery, EmptyScorer, Explanation, Occur, Query, Scorer, TermQuery, Weight},
+    schema::IndexRecordOption,
+    DocId, DocSet, Postings, Result, Score, Searcher, SegmentReader, SkipResult, TantivyError,
+    Term,
+};
+
+/// A PhraseQuery that tolerates up to `slop` moves of its terms
+///
+/// So `"chicken thighs"` with a slop of 1 also matches "chicken and
+/// thighs" and, with a slop of 2, "thighs chicken". Matching documents
+/// are scored as if every term was required individually.
+#[derive(Debug, Clone)]
+pub(crate) struct SloppyPhraseQuery {
+    terms: Vec<Term>,
+    slop: u32,
+}
+
+impl SloppyPhraseQuery {
+    pub fn new(terms: Vec<Term>, slop: u32) -> Self {
+        debug_assert!(terms.len() > 1);
+        debug_assert!(terms.windows(2).all(|w| w[0].field() == w[1].field()));
+        Self { terms, slop }
+    }
+}
+
+impl Query for SloppyPhraseQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        let field = self.terms[0].field();
+        let field_entry = searcher.schema().get_field_entry(field);
+
+        let has_positions = field_entry
+            .field_type()
+            .get_index_record_option()
+            .map_or(false, |option| option.has_positions());
+
+        if !has_positions {
+            return Err(TantivyError::SchemaError(format!(
+                "Field {} is not indexed with positions",
+                field_entry.name()
+            )));
+        }
+
+        let conjunction = BooleanQuery::from(
+            self.terms
+                .iter()
+                .map(|term| {
+                    let query: Box<dyn Query> =
+                        Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs));
+                    (Occur::Must, query)
+                })
+                .collect::<Vec<_>>(),
+        )
+        .weight(searcher, scoring_enabled)?;
+
+        // Every term is identified by the first of its copies
+        let copy_of = self
+            .terms
+            .iter()
+            .map(|term| {
+                self.terms
+                    .iter()
+                    .position(|other| other == term)
+                    .expect("term is part of the phrase")
+            })
+            .collect();
+
+        Ok(Box::new(SloppyPhraseWeight {
+            terms: self.terms.clone(),
+            copy_of,
+            slop: self.slop,
+            conjunction,
+        }))
+    }
+}
+
+struct SloppyPhraseWeight {
+    terms: Vec<Term>,
+    copy_of: Vec<usize>,
+    slop: u32,
+    conjunction: Box<dyn Weight>,
+}
+
+impl Weight for SloppyPhraseWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        let inverted_index = reader.inverted_index(self.terms[0].field());
+
+        let mut postings = Vec::with_capacity(self.terms.len());
+        for term in &self.terms {
+            if let Some(term_postings) =
+                inverted_index.read_postings(term, IndexRecordOption::WithFreqsAndPositions)
+            {
+                postings.push(term_postings);
+            } else {
+                return Ok(Box::new(EmptyScorer));
+            }
+        }
+
+        Ok(Box::new(SloppyPhraseScorer {
+            conjunction: self.conjunction.scorer(reader, boost)?,
+            positions: vec![Vec::new(); postings.len()],
+            postings,
+            copy_of: self.copy_of.clone(),
+            slop: self.slop,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.skip_next(doc) != SkipResult::Reached {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        let mut explanation = Explanation::new(
+            format!("SloppyPhraseQuery. Slop = {}", self.slop),
+            scorer.score(),
+        );
+        explanation.add_detail(self.conjunction.explain(reader, doc)?);
+
+        Ok(explanation)
+    }
+}
+
+struct SloppyPhraseScorer {
+    conjunction: Box<dyn Scorer>,
+    postings: Vec<SegmentPostings>,
+    positions: Vec<Vec<u32>>,
+    copy_of: Vec<usize>,
+    slop: u32,
+}
+
+impl SloppyPhraseScorer {
+    fn phrase_match(&mut self) -> bool {
+        let doc = self.conjunction.doc();
+
+        for (postings, positions) in self.postings.iter_mut().zip(self.positions.iter_mut()) {
+            if postings.skip_next(doc) != SkipResult::Reached {
+                return false;
+            }
+            postings.positions(positions);
+        }
+
+        within_slop(&self.positions, &self.copy_of, self.slop)
+    }
+}
+
+impl Scorer for SloppyPhraseScorer {
+    fn score(&mut self) -> Score {
+        self.conjunction.score()
+    }
+}
+
+impl DocSet for SloppyPhraseScorer {
+    fn advance(&mut self) -> bool {
+        while self.conjunction.advance() {
+            if self.phrase_match() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn doc(&self) -> DocId {
+        self.conjunction.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.conjunction.size_hint()
+    }
+}
+
+/// Whether there's a choice of one position per term such that moving
+/// the terms at most `slop` times puts them in order, next to each other
+///
+/// Subtracting its offset in the phrase from each position, an exact
+/// match is when every term ends up at the same place, so what's
+/// checked is whether the smallest window covering one (adjusted)
+/// position of every term is at most `slop` wide.
+///
+/// `copy_of` tells, for every term, the offset of the first term
+/// that is the same as it. Repeated terms must each be found at a
+/// different position, like they would in an exact match.
+fn within_slop(positions: &[Vec<u32>], copy_of: &[usize], slop: u32) -> bool {
+    if positions.iter().any(Vec::is_empty) {
+        return false;
+    }
+
+    if copy_of
+        .iter()
+        .enumerate()
+        .any(|(offset, &copy)| offset != copy)
+    {
+        return within_slop_repeated(positions, copy_of, slop);
+    }
+
+    let mut cursors = vec![0; positions.len()];
+
+    loop {
+        let mut min = (i64::MAX, 0);
+        let mut max = i64::MIN;
+
+        for (offset, (term_positions, &cursor)) in positions.iter().zip(cursors.iter()).enumerate()
+        {
+            let adjusted = i64::from(term_positions[cursor]) - offset as i64;
+            if adjusted < min.0 {
+                min = (adjusted, offset);
+            }
+            max = max.max(adjusted);
+        }
+
+        if max - min.0 <= i64::from(slop) {
+            return true;
+        }
+
+        // Only moving past the leftmost position can shrink the window
+        let (_, offset) = min;
+        cursors[offset] += 1;
+        if cursors[offset] == positions[offset].len() {
+            return false;
+        }
+    }
+}
+
+/// Same as `within_slop`, for phrases that repeat terms: every
+/// window of adjusted positions starting at one of them is checked
+/// for whether each term can take a position of its own within it
+fn within_slop_repeated(positions: &[Vec<u32>], copy_of: &[usize], slop: u32) -> bool {
+    let mut starts = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(offset, term_positions)| {
+            term_positions
+                .iter()
+                .map(move |&pos| i64::from(pos) - offset as i64)
+        })
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup();
+
+    starts.into_iter().any(|start| {
+        // The last position taken by each repeated term
+        let mut taken: Vec<Option<u32>> = vec![None; positions.len()];
+
+        positions
+            .iter()
+            .enumerate()
+            .all(|(offset, term_positions)| {
+                let lowest = start + offset as i64;
+                let highest = lowest + i64::from(slop);
+
+                // Copies come in order, so taking the lowest position
+                // available leaves the most room for the next ones
+                let lowest = taken[copy_of[offset]]
+                    .map_or(lowest, |last| lowest.max(i64::from(last) + 1))
+                    .max(0);
+                let idx = match term_positions.binary_search(&(lowest as u32)) {
+                    Ok(idx) | Err(idx) => idx,
+                };
+
+                match term_positions.get(idx) {
+                    Some(&pos) if i64::from(pos) <= highest => {
+                        taken[copy_of[offset]] = Some(pos);
+                        true
+                    }
+                    _ => false,
+                }
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::TopDocs,
+        doc,
+        schema::{SchemaBuilder, TEXT},
+        DocAddress, Index,
+    };
+
+    #[test]
+    fn slop_matching() {
+        let distinct = [0, 1, 2];
+        // "chicken thighs"
+        assert!(within_slop(&[vec![0], vec![1]], &distinct[..2], 0));
+        // "chicken and thighs"
+        assert!(!within_slop(&[vec![0], vec![2]], &distinct[..2], 0));
+        assert!(within_slop(&[vec![0], vec![2]], &distinct[..2], 1));
+        // "thighs chicken"
+        assert!(!within_slop(&[vec![1], vec![0]], &distinct[..2], 1));
+        assert!(within_slop(&[vec![1], vec![0]], &distinct[..2], 2));
+        // The best window isn't the first one
+        assert!(within_slop(
+            &[vec![0, 10], vec![5, 12], vec![13]],
+            &distinct,
+            1
+        ));
+        assert!(!within_slop(
+            &[vec![0, 10], vec![5, 12], vec![20]],
+            &distinct,
+            1
+        ));
+    }
+
+    #[test]
+    fn repeated_terms_need_positions_of_their_own() {
+        // "no no" against "no"
+        let twice = [0, 0];
+        assert!(!within_slop(&[vec![0], vec![0]], &twice, 1));
+        assert!(!within_slop(&[vec![0], vec![0]], &twice, 5));
+        // "no no"
+        assert!(within_slop(&[vec![0, 1], vec![0, 1]], &twice, 0));
+        // "no way no"
+        assert!(!within_slop(&[vec![0, 2], vec![0, 2]], &twice, 0));
+        assert!(within_slop(&[vec![0, 2], vec![0, 2]], &twice, 1));
+
+        // "no thanks no" against "thanks no": "no" can't be at
+        // both ends at once
+        let around = [0, 1, 0];
+        assert!(!within_slop(&[vec![1], vec![0], vec![1]], &around, 2));
+        assert!(within_slop(&[vec![1, 3], vec![0], vec![1, 3]], &around, 3));
+        // "no thanks no" itself, within a longer text
+        assert!(within_slop(
+            &[vec![4, 9, 11], vec![10], vec![4, 9, 11]],
+            &around,
+            0
+        ));
+    }
+
+    #[test]
+    fn repeated_terms_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "no"));
+        writer.add_document(doc!(field => "no way"));
+        writer.add_document(doc!(field => "no way no"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let no = Term::from_field_text(field, "no");
+        let query = SloppyPhraseQuery::new(vec![no.clone(), no], 1);
+        let found = searcher.search(&query, &TopDocs::with_limit(3))?;
+
+        assert_eq!(
+            vec![DocAddress(0, 2)],
+            found
+                .into_iter()
+                .map(|(_score, addr)| addr)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
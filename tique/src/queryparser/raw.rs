@@ -1,24 +1,45 @@
 use nom::{
     self,
     branch::alt,
-    bytes::complete::take_while1,
-    character::complete::{char as is_char, multispace0},
-    combinator::{map, map_res},
+    bytes::complete::{tag, take_while, take_while1},
+    character::complete::{char as is_char, digit1, multispace0, multispace1},
+    combinator::{map, map_res, opt, recognize},
+    error::ErrorKind,
     multi::many0,
-    sequence::{delimited, preceded, separated_pair},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
+use std::ops::Bound;
+
 use tantivy::query::Occur;
 
+use super::wildcard::WILDCARD;
+
 #[derive(Debug, PartialEq)]
 pub struct RawQuery<'a> {
     pub input: &'a str,
     pub is_phrase: bool,
     pub field_name: Option<&'a str>,
     pub occur: Occur,
+    /// Maximum edit distance when matching a term
+    pub fuzziness: Option<u8>,
+    /// Whether `input` is a pattern with `*` wildcards
+    pub is_wildcard: bool,
+    /// How much more (or less) important than the rest of the query
+    pub boost: Option<f32>,
+    /// How many moves a phrase may need to match
+    pub slop: Option<u32>,
 }
 
-const FIELD_SEP: char = ':';
+pub(crate) const FIELD_SEP: char = ':';
+const FUZZY_SEP: char = '~';
+const BOOST_SEP: char = '^';
+
+/// Largest edit distance accepted in the `term~N` syntax
+pub const MAX_FUZZINESS: u8 = 2;
+/// How deep groups may be nested. Parentheses past that are taken
+/// literally, keeping the recursion (and the stack) in check
+const MAX_NESTING: usize = 16;
 
 impl<'a> RawQuery<'a> {
     pub fn new(input: &'a str) -> Self {
@@ -27,6 +48,10 @@ impl<'a> RawQuery<'a> {
             is_phrase: false,
             field_name: None,
             occur: Occur::Should,
+            fuzziness: None,
+            is_wildcard: false,
+            boost: None,
+            slop: None,
         }
     }
 
@@ -53,10 +78,169 @@ impl<'a> RawQuery<'a> {
         self.field_name = Some(name);
         self
     }
+
+    pub fn fuzzy(mut self, distance: u8) -> Self {
+        debug_assert!(!self.is_phrase);
+        self.fuzziness = Some(distance);
+        self
+    }
+
+    pub fn wildcard(mut self) -> Self {
+        debug_assert!(!self.is_phrase);
+        self.is_wildcard = true;
+        self
+    }
+
+    pub fn slop(mut self, slop: u32) -> Self {
+        debug_assert!(self.is_phrase);
+        self.slop = Some(slop);
+        self
+    }
+
+    pub fn boost(mut self, boost: f32) -> Self {
+        debug_assert_eq!(None, self.boost);
+        self.boost = Some(boost);
+        self
+    }
+}
+
+/// A comparison against a numeric field, like `calories:<400` or
+/// `total_time:10..30`. Bounds are kept as written: only the parser
+/// knows which numeric type the field holds
+#[derive(Debug, PartialEq)]
+pub struct RawRange<'a> {
+    pub field_name: &'a str,
+    pub lower: Bound<&'a str>,
+    pub upper: Bound<&'a str>,
+    pub occur: Occur,
+}
+
+impl<'a> RawRange<'a> {
+    pub fn new(field_name: &'a str, lower: Bound<&'a str>, upper: Bound<&'a str>) -> Self {
+        Self {
+            field_name,
+            lower,
+            upper,
+            occur: Occur::Should,
+        }
+    }
+
+    pub fn must_not(mut self) -> Self {
+        debug_assert_eq!(Occur::Should, self.occur);
+        self.occur = Occur::MustNot;
+        self
+    }
+
+    pub fn must(mut self) -> Self {
+        debug_assert_eq!(Occur::Should, self.occur);
+        self.occur = Occur::Must;
+        self
+    }
+}
+
+/// A parenthesized sequence of clauses
+#[derive(Debug, PartialEq)]
+pub struct RawGroup<'a> {
+    pub clauses: Vec<RawClause<'a>>,
+    pub occur: Occur,
+}
+
+impl<'a> RawGroup<'a> {
+    pub fn new(clauses: Vec<RawClause<'a>>) -> Self {
+        Self {
+            clauses,
+            occur: Occur::Should,
+        }
+    }
+
+    pub fn must_not(mut self) -> Self {
+        debug_assert_eq!(Occur::Should, self.occur);
+        self.occur = Occur::MustNot;
+        self
+    }
+
+    pub fn must(mut self) -> Self {
+        debug_assert_eq!(Occur::Should, self.occur);
+        self.occur = Occur::Must;
+        self
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RawClause<'a> {
+    Query(RawQuery<'a>),
+    Range(RawRange<'a>),
+    Group(RawGroup<'a>),
+}
+
+impl<'a> RawClause<'a> {
+    pub fn occur(&self) -> Occur {
+        match self {
+            RawClause::Query(query) => query.occur,
+            RawClause::Range(range) => range.occur,
+            RawClause::Group(group) => group.occur,
+        }
+    }
+
+    fn set_occur(&mut self, occur: Occur) {
+        match self {
+            RawClause::Query(query) => query.occur = occur,
+            RawClause::Range(range) => range.occur = occur,
+            RawClause::Group(group) => group.occur = occur,
+        }
+    }
+
+    pub fn must_not(self) -> Self {
+        match self {
+            RawClause::Query(query) => query.must_not().into(),
+            RawClause::Range(range) => range.must_not().into(),
+            RawClause::Group(group) => group.must_not().into(),
+        }
+    }
+
+    pub fn must(self) -> Self {
+        match self {
+            RawClause::Query(query) => query.must().into(),
+            RawClause::Range(range) => range.must().into(),
+            RawClause::Group(group) => group.must().into(),
+        }
+    }
+
+    /// Turns an optional clause into a mandatory one, leaving the
+    /// clauses that already have an explicit occur alone
+    fn require(&mut self) {
+        if self.occur() == Occur::Should {
+            self.set_occur(Occur::Must);
+        }
+    }
+}
+
+impl<'a> From<RawQuery<'a>> for RawClause<'a> {
+    fn from(query: RawQuery<'a>) -> Self {
+        RawClause::Query(query)
+    }
+}
+
+impl<'a> From<RawRange<'a>> for RawClause<'a> {
+    fn from(range: RawRange<'a>) -> Self {
+        RawClause::Range(range)
+    }
+}
+
+impl<'a> From<RawGroup<'a>> for RawClause<'a> {
+    fn from(group: RawGroup<'a>) -> Self {
+        RawClause::Group(group)
+    }
 }
 
 pub trait FieldNameValidator {
     fn check(&self, field_name: &str) -> bool;
+    /// Whether `field_name` accepts comparisons like `field:<10`
+    fn check_range(&self, field_name: &str) -> bool;
+    /// How to treat clauses without a `+` or `-` prefix
+    fn default_occur(&self) -> Occur {
+        Occur::Should
+    }
 }
 
 impl<T> FieldNameValidator for Vec<T>
@@ -66,62 +250,305 @@ where
     fn check(&self, field_name: &str) -> bool {
         self.iter().any(|item| item == &field_name)
     }
+
+    fn check_range(&self, field_name: &str) -> bool {
+        self.check(field_name)
+    }
 }
 
 impl FieldNameValidator for bool {
     fn check(&self, _field_name: &str) -> bool {
         *self
     }
+
+    fn check_range(&self, _field_name: &str) -> bool {
+        *self
+    }
 }
 
 pub fn parse_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
-) -> IResult<&'a str, Vec<RawQuery<'a>>> {
-    many0(delimited(
-        multispace0,
-        alt((
-            |input| prohibited_query(input, validator),
-            |input| mandatory_query(input, validator),
-            |input| field_prefixed_query(input, validator),
-            any_field_query,
+) -> IResult<&'a str, Vec<RawClause<'a>>> {
+    clauses(input, validator, 0)
+}
+
+/// The clauses of a query within `depth` groups
+fn clauses<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, Vec<RawClause<'a>>> {
+    map(
+        many0(delimited(
+            multispace0,
+            alt((
+                operator,
+                map(|input| clause(input, validator, depth), Item::Clause),
+                // An unbalanced closing parenthesis is just part of a term
+                map(
+                    recognize(pair(is_char(')'), take_while(is_term_char))),
+                    |s| Item::Clause(RawQuery::new(s).into()),
+                ),
+            )),
+            multispace0,
         )),
-        multispace0,
+        move |items| resolve_operators(items, validator.default_occur()),
+    )(input)
+}
+
+enum Item<'a> {
+    Clause(RawClause<'a>),
+    And(&'a str),
+    Or(&'a str),
+}
+
+fn operator(input: &str) -> IResult<&str, Item> {
+    terminated(
+        alt((map(tag("AND"), Item::And), map(tag("OR"), Item::Or))),
+        multispace1,
+    )(input)
+}
+
+/// `a AND b` makes both `a` and `b` mandatory and `a OR b` keeps both
+/// optional, which is the same as `a b` unless `default_occur` is
+/// `Must`. An operator that isn't between two clauses is taken
+/// literally, as a term
+fn resolve_operators(items: Vec<Item>, default_occur: Occur) -> Vec<RawClause> {
+    let mut clauses: Vec<RawClause> = Vec::with_capacity(items.len());
+    // Whether each clause was joined to another with `OR`
+    let mut optional = Vec::with_capacity(items.len());
+    let mut require_next = false;
+    let mut optional_next = false;
+    let mut after_clause = false;
+
+    let mut items = items.into_iter().peekable();
+    while let Some(item) = items.next() {
+        let before_clause = matches!(items.peek(), Some(Item::Clause(_)));
+
+        match item {
+            Item::Clause(mut clause) => {
+                if require_next {
+                    clause.require();
+                    require_next = false;
+                }
+                clauses.push(clause);
+                optional.push(optional_next);
+                optional_next = false;
+            }
+            Item::And(_) if after_clause && before_clause => {
+                if let Some(previous) = clauses.last_mut() {
+                    previous.require();
+                }
+                require_next = true;
+                continue;
+            }
+            Item::Or(_) if after_clause && before_clause => {
+                if let Some(previous) = optional.last_mut() {
+                    *previous = true;
+                }
+                optional_next = true;
+                continue;
+            }
+            Item::And(literal) | Item::Or(literal) => {
+                clauses.push(RawQuery::new(literal).into());
+                optional.push(false);
+            }
+        }
+
+        after_clause = true;
+    }
+
+    if default_occur == Occur::Must {
+        for (clause, optional) in clauses.iter_mut().zip(optional) {
+            if !optional {
+                clause.require();
+            }
+        }
+    }
+
+    clauses
+}
+
+fn clause<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, RawClause<'a>> {
+    alt((
+        |input| prohibited_clause(input, validator, depth),
+        |input| mandatory_clause(input, validator, depth),
+        |input| unprefixed_clause(input, validator, depth),
     ))(input)
 }
 
-fn prohibited_query<'a, C: FieldNameValidator>(
+fn unprefixed_clause<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
-) -> IResult<&'a str, RawQuery<'a>> {
+    depth: usize,
+) -> IResult<&'a str, RawClause<'a>> {
+    alt((
+        |input| group(input, validator, depth),
+        map(|input| range_query(input, validator), RawClause::from),
+        map(
+            |input| field_prefixed_query(input, validator),
+            RawClause::from,
+        ),
+        map(any_field_query, RawClause::from),
+    ))(input)
+}
+
+fn prohibited_clause<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, RawClause<'a>> {
     map(
         preceded(
-            is_char('-'),
-            alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
-            )),
+            alt((tag("-"), terminated(tag("NOT"), multispace1))),
+            |input| unprefixed_clause(input, validator, depth),
         ),
-        RawQuery::must_not,
+        RawClause::must_not,
     )(input)
 }
 
-fn mandatory_query<'a, C: FieldNameValidator>(
+fn mandatory_clause<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
-) -> IResult<&'a str, RawQuery<'a>> {
+    depth: usize,
+) -> IResult<&'a str, RawClause<'a>> {
     map(
-        preceded(
-            is_char('+'),
-            alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
-            )),
+        preceded(is_char('+'), |input| {
+            unprefixed_clause(input, validator, depth)
+        }),
+        RawClause::must,
+    )(input)
+}
+
+/// A parenthesized sequence of clauses. Only attempted when the opening
+/// parenthesis has a matching closing one without nesting deeper than
+/// `MAX_NESTING` so that unbalanced (or too deep) input quickly falls
+/// back to being parsed as terms
+fn group<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, RawClause<'a>> {
+    let end = closing_paren(input, MAX_NESTING.saturating_sub(depth))
+        .ok_or(nom::Err::Error((input, ErrorKind::Char)))?;
+
+    let (remaining, clauses) = clauses(&input[1..end], validator, depth + 1)?;
+    if !remaining.is_empty() {
+        return Err(nom::Err::Error((remaining, ErrorKind::Eof)));
+    }
+
+    Ok((&input[end + 1..], RawGroup::new(clauses).into()))
+}
+
+/// Position of the parenthesis that closes the one `input` starts with,
+/// unless parentheses get nested more than `max_depth` levels deep
+/// before that
+fn closing_paren(input: &str, max_depth: usize) -> Option<usize> {
+    if !input.starts_with('(') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut in_phrase = false;
+    for (pos, c) in input.char_indices() {
+        match c {
+            '"' => in_phrase = !in_phrase,
+            '(' if !in_phrase => {
+                depth += 1;
+                if depth > max_depth {
+                    return None;
+                }
+            }
+            ')' if !in_phrase => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn range_query<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+) -> IResult<&'a str, RawRange<'a>> {
+    map_res(
+        separated_pair(
+            take_while1(|c| c != FIELD_SEP && is_term_char(c)),
+            is_char(FIELD_SEP),
+            take_while1(is_term_char),
         ),
-        RawQuery::must,
+        |(name, range)| {
+            if !validator.check_range(name) {
+                return Err("Invalid field");
+            }
+
+            let (lower, upper) = split_range(range).ok_or("Invalid range")?;
+            Ok(RawRange::new(name, lower, upper))
+        },
     )(input)
 }
 
+/// Understands `<N`, `<=N`, `>N`, `>=N`, `A..B`, `A..=B`, `A..`
+/// and `..B`. Like the ranges in the JSON filter, `A..B` doesn't
+/// include `B`
+fn split_range(input: &str) -> Option<(Bound<&str>, Bound<&str>)> {
+    let (lower, upper) = if let Some(value) = input.strip_prefix("<=") {
+        (Bound::Unbounded, Bound::Included(value))
+    } else if let Some(value) = input.strip_prefix('<') {
+        (Bound::Unbounded, Bound::Excluded(value))
+    } else if let Some(value) = input.strip_prefix(">=") {
+        (Bound::Included(value), Bound::Unbounded)
+    } else if let Some(value) = input.strip_prefix('>') {
+        (Bound::Excluded(value), Bound::Unbounded)
+    } else {
+        let pos = input.find("..")?;
+        let (start, end) = (&input[..pos], &input[pos + 2..]);
+
+        let lower = if start.is_empty() {
+            Bound::Unbounded
+        } else {
+            Bound::Included(start)
+        };
+
+        let upper = if let Some(end) = end.strip_prefix('=') {
+            Bound::Included(end)
+        } else if end.is_empty() {
+            Bound::Unbounded
+        } else {
+            Bound::Excluded(end)
+        };
+
+        if lower == Bound::Unbounded && upper == Bound::Unbounded {
+            return None;
+        }
+
+        (lower, upper)
+    };
+
+    let is_number = |bound: &Bound<&str>| match bound {
+        Bound::Included(value) | Bound::Excluded(value) => {
+            value.parse::<f64>().map_or(false, f64::is_finite)
+        }
+        Bound::Unbounded => true,
+    };
+
+    if is_number(&lower) && is_number(&upper) {
+        Some((lower, upper))
+    } else {
+        None
+    }
+}
+
 fn field_prefixed_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
@@ -148,25 +575,130 @@ fn any_field_query(input: &str) -> IResult<&str, RawQuery> {
 
 fn parse_phrase(input: &str) -> IResult<&str, RawQuery> {
     map(
-        delimited(is_char('"'), take_while1(|c| c != '"'), is_char('"')),
-        |s| RawQuery::new(s).phrase(),
+        tuple((
+            delimited(is_char('"'), take_while1(|c| c != '"'), is_char('"')),
+            opt(preceded(
+                is_char(FUZZY_SEP),
+                map_res(digit1, |slop: &str| slop.parse::<u32>()),
+            )),
+            opt(preceded(is_char(BOOST_SEP), parse_boost)),
+        )),
+        |(s, slop, boost)| {
+            let mut query = RawQuery::new(s).phrase();
+            if let Some(slop) = slop {
+                query = query.slop(slop);
+            }
+            if let Some(boost) = boost {
+                query.boost(boost)
+            } else {
+                query
+            }
+        },
     )(input)
 }
 
 fn parse_term(input: &str) -> IResult<&str, RawQuery> {
-    map(take_while1(is_term_char), RawQuery::new)(input)
+    map(take_while1(is_term_char), |term: &str| {
+        let (term, boost) = split_boost(term).map_or((term, None), |(t, b)| (t, Some(b)));
+
+        let query = if let Some((term, distance)) = split_fuzziness(term) {
+            RawQuery::new(term).fuzzy(distance)
+        } else if is_wildcard_pattern(term) {
+            RawQuery::new(term).wildcard()
+        } else {
+            RawQuery::new(term)
+        };
+
+        if let Some(boost) = boost {
+            query.boost(boost)
+        } else {
+            query
+        }
+    })(input)
+}
+
+/// A finite non-negative number made of digits and, optionally, a dot
+fn parse_boost(input: &str) -> IResult<&str, f32> {
+    map_res(
+        take_while1(|c: char| c.is_ascii_digit() || c == '.'),
+        |boost: &str| match boost.parse::<f32>() {
+            Ok(boost) if boost.is_finite() => Ok(boost),
+            _ => Err(ErrorKind::MapRes),
+        },
+    )(input)
+}
+
+/// Splits `term^N` into the term and its boost
+fn split_boost(input: &str) -> Option<(&str, f32)> {
+    let pos = input.rfind(BOOST_SEP)?;
+    let (term, boost) = (&input[..pos], &input[pos + 1..]);
+
+    if term.is_empty() {
+        return None;
+    }
+
+    match parse_boost(boost) {
+        Ok(("", boost)) => Some((term, boost)),
+        _ => None,
+    }
+}
+
+/// A term with a `*` anywhere but at its start, like `choco*`. A
+/// leading wildcard would need to look at every term in the index, so
+/// `*late` is kept as a regular term
+fn is_wildcard_pattern(input: &str) -> bool {
+    !input.starts_with(WILDCARD) && input.contains(WILDCARD)
+}
+
+/// Splits `term~` and `term~N` into the term and its edit distance:
+/// 1 when not specified, at most `MAX_FUZZINESS`
+fn split_fuzziness(input: &str) -> Option<(&str, u8)> {
+    let pos = input.rfind(FUZZY_SEP)?;
+    let (term, distance) = (&input[..pos], &input[pos + 1..]);
+
+    if term.is_empty() || !distance.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let distance = if distance.is_empty() {
+        1
+    } else {
+        distance
+            .parse::<u8>()
+            .unwrap_or(MAX_FUZZINESS)
+            .min(MAX_FUZZINESS)
+    };
+
+    Some((term, distance))
 }
 
 fn is_term_char(c: char) -> bool {
-    !(c == ' ' || c == '\t' || c == '\r' || c == '\n')
+    !(c == ' ' || c == '\t' || c == '\r' || c == '\n' || c == ')')
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Parses input that's expected to yield no groups
+    fn flat_parse<'a, C: FieldNameValidator>(
+        input: &'a str,
+        validator: &'a C,
+    ) -> IResult<&'a str, Vec<RawQuery<'a>>> {
+        parse_query(input, validator).map(|(remaining, clauses)| {
+            let queries = clauses
+                .into_iter()
+                .map(|clause| match clause {
+                    RawClause::Query(query) => query,
+                    other => panic!("Unexpected clause: {:?}", other),
+                })
+                .collect();
+            (remaining, queries)
+        })
+    }
+
     fn parse_no_fields(input: &str) -> IResult<&str, Vec<RawQuery>> {
-        parse_query(input, &false)
+        flat_parse(input, &false)
     }
 
     #[test]
@@ -242,7 +774,7 @@ mod tests {
 
         // No field support: fields end up in the term
         assert_eq!(
-            parse_query(input, &false),
+            flat_parse(input, &false),
             Ok((
                 "",
                 vec![
@@ -254,7 +786,7 @@ mod tests {
 
         // Any field support: field names are not valitdated at all
         assert_eq!(
-            parse_query(input, &true),
+            flat_parse(input, &true),
             Ok((
                 "",
                 vec![
@@ -267,7 +799,7 @@ mod tests {
         // Strict field support: known fields are identified, unknown
         // ones are part of the term
         assert_eq!(
-            parse_query(input, &vec!["ingredient"]),
+            flat_parse(input, &vec!["ingredient"]),
             Ok((
                 "",
                 vec![
@@ -281,7 +813,7 @@ mod tests {
     #[test]
     fn garbage_handling() {
         assert_eq!(
-            parse_query("- -field: -\"\" body:\"\"", &true),
+            flat_parse("- -field: -\"\" body:\"\"", &true),
             Ok((
                 "",
                 vec![
@@ -297,7 +829,7 @@ mod tests {
     #[test]
     fn parse_term_with_field() {
         assert_eq!(
-            parse_query("title:potato:queen +instructions:mash -body:\"how to fail\" ingredient:\"golden peeler\"", &true),
+            flat_parse("title:potato:queen +instructions:mash -body:\"how to fail\" ingredient:\"golden peeler\"", &true),
             Ok((
                 "",
                 vec![
@@ -310,6 +842,414 @@ mod tests {
         );
     }
 
+    fn group(clauses: Vec<RawClause>) -> RawClause {
+        RawGroup::new(clauses).into()
+    }
+
+    fn term(input: &str) -> RawClause {
+        RawQuery::new(input).into()
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(
+            parse_query("(bacon pancetta) -(egg \"fried egg\") +(ham)", &false),
+            Ok((
+                "",
+                vec![
+                    group(vec![term("bacon"), term("pancetta")]),
+                    group(vec![
+                        term("egg"),
+                        RawQuery::new("fried egg").phrase().into()
+                    ])
+                    .must_not(),
+                    group(vec![term("ham")]).must(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn nested_grouping() {
+        assert_eq!(
+            parse_query("((a b) -c)", &false),
+            Ok((
+                "",
+                vec![group(vec![
+                    group(vec![term("a"), term("b")]),
+                    term("c").must_not()
+                ])]
+            ))
+        );
+    }
+
+    #[test]
+    fn fields_inside_groups() {
+        assert_eq!(
+            parse_query("(title:banana -\"a)b\")", &true),
+            Ok((
+                "",
+                vec![group(vec![
+                    RawQuery::new("banana").with_field("title").into(),
+                    RawQuery::new("a)b").phrase().must_not().into(),
+                ])]
+            ))
+        );
+    }
+
+    #[test]
+    fn boolean_operators() {
+        assert_eq!(
+            parse_query("(bacon OR pancetta) AND -egg", &false),
+            Ok((
+                "",
+                vec![
+                    group(vec![term("bacon"), term("pancetta")]).must(),
+                    term("egg").must_not()
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("a AND b c AND +d NOT e", &false),
+            Ok((
+                "",
+                vec![
+                    term("a").must(),
+                    term("b").must(),
+                    term("c").must(),
+                    term("d").must(),
+                    term("e").must_not(),
+                ]
+            ))
+        );
+    }
+
+    struct RequireAll;
+
+    impl FieldNameValidator for RequireAll {
+        fn check(&self, _field_name: &str) -> bool {
+            false
+        }
+
+        fn check_range(&self, _field_name: &str) -> bool {
+            false
+        }
+
+        fn default_occur(&self) -> Occur {
+            Occur::Must
+        }
+    }
+
+    #[test]
+    fn default_occur() {
+        assert_eq!(
+            parse_query("a -b (c d) e OR f OR g +h", &RequireAll),
+            Ok((
+                "",
+                vec![
+                    term("a").must(),
+                    term("b").must_not(),
+                    group(vec![term("c").must(), term("d").must()]).must(),
+                    term("e"),
+                    term("f"),
+                    term("g"),
+                    term("h").must(),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("(bacon OR pancetta) egg", &RequireAll),
+            Ok((
+                "",
+                vec![
+                    group(vec![term("bacon"), term("pancetta")]).must(),
+                    term("egg").must(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn misplaced_operators_are_terms() {
+        assert_eq!(
+            parse_no_fields("AND rock OR "),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("AND"),
+                    RawQuery::new("rock"),
+                    RawQuery::new("OR"),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("rock NOT"),
+            Ok(("", vec![RawQuery::new("rock"), RawQuery::new("NOT")]))
+        );
+
+        // Only uppercase whole words are operators
+        assert_eq!(
+            parse_no_fields("and ANDROID NOTE"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("and"),
+                    RawQuery::new("ANDROID"),
+                    RawQuery::new("NOTE"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn unbalanced_parens_are_terms() {
+        assert_eq!(
+            parse_no_fields("(bacon OR pancetta"),
+            Ok(("", vec![RawQuery::new("(bacon"), RawQuery::new("pancetta")]))
+        );
+
+        assert_eq!(
+            parse_no_fields("bacon) -egg)"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("bacon"),
+                    RawQuery::new(")"),
+                    RawQuery::new("egg").must_not(),
+                    RawQuery::new(")"),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("(a (b)", &false),
+            Ok(("", vec![term("(a"), group(vec![term("b")])]))
+        );
+    }
+
+    #[test]
+    fn fuzzy_term_extraction() {
+        assert_eq!(
+            parse_query("parmesean~ +zuchini~2 title:aubergin~0 chilli~9", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("parmesean").fuzzy(1).into(),
+                    RawQuery::new("zuchini").fuzzy(2).must().into(),
+                    RawQuery::new("aubergin")
+                        .fuzzy(0)
+                        .with_field("title")
+                        .into(),
+                    RawQuery::new("chilli").fuzzy(MAX_FUZZINESS).into(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn fuzzy_lookalikes_are_terms() {
+        assert_eq!(
+            parse_no_fields("~ ~1 a~b c~~ ~~d"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("~"),
+                    RawQuery::new("~1"),
+                    RawQuery::new("a~b"),
+                    RawQuery::new("c~").fuzzy(1),
+                    RawQuery::new("~~d"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn wildcard_term_extraction() {
+        assert_eq!(
+            parse_query("choco* -ingredients:choc*late \"cream*\" *late", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("choco*").wildcard().into(),
+                    RawQuery::new("choc*late")
+                        .wildcard()
+                        .with_field("ingredients")
+                        .must_not()
+                        .into(),
+                    RawQuery::new("cream*").phrase().into(),
+                    RawQuery::new("*late").into(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn range_extraction() {
+        use Bound::*;
+        let validator = vec!["calories", "total_time"];
+
+        assert_eq!(
+            parse_query(
+                "pasta calories:<400 total_time:10..30 -calories:>=1e3 +total_time:..=5",
+                &validator
+            ),
+            Ok((
+                "",
+                vec![
+                    term("pasta"),
+                    RawRange::new("calories", Unbounded, Excluded("400")).into(),
+                    RawRange::new("total_time", Included("10"), Excluded("30")).into(),
+                    RawRange::new("calories", Included("1e3"), Unbounded)
+                        .must_not()
+                        .into(),
+                    RawRange::new("total_time", Unbounded, Included("5"))
+                        .must()
+                        .into(),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_query("(calories:<=1.5 OR calories:-2..)", &validator),
+            Ok((
+                "",
+                vec![group(vec![
+                    RawRange::new("calories", Unbounded, Included("1.5")).into(),
+                    RawRange::new("calories", Included("-2"), Unbounded).into(),
+                ])]
+            ))
+        );
+    }
+
+    #[test]
+    fn range_lookalikes_are_terms() {
+        assert_eq!(
+            parse_query(
+                "calories:<lots calories:.. calories:1..2..3 calories:<inf title:<5",
+                &vec!["calories"]
+            ),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("<lots").with_field("calories").into(),
+                    RawQuery::new("..").with_field("calories").into(),
+                    RawQuery::new("1..2..3").with_field("calories").into(),
+                    RawQuery::new("<inf").with_field("calories").into(),
+                    term("title:<5"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn boost_extraction() {
+        assert_eq!(
+            parse_query(
+                "bacon^2 cheese \"deep fry\"^0.5 -title:egg^3 parmesean~^1.5 choco*^.5",
+                &true
+            ),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("bacon").boost(2.0).into(),
+                    term("cheese"),
+                    RawQuery::new("deep fry").phrase().boost(0.5).into(),
+                    RawQuery::new("egg")
+                        .with_field("title")
+                        .boost(3.0)
+                        .must_not()
+                        .into(),
+                    RawQuery::new("parmesean").fuzzy(1).boost(1.5).into(),
+                    RawQuery::new("choco*").wildcard().boost(0.5).into(),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn boost_lookalikes_are_terms() {
+        assert_eq!(
+            parse_no_fields("^2 a^ b^c c^-1 d^1.2.3 \"e\"^x"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("^2"),
+                    RawQuery::new("a^"),
+                    RawQuery::new("b^c"),
+                    RawQuery::new("c^-1"),
+                    RawQuery::new("d^1.2.3"),
+                    RawQuery::new("e").phrase(),
+                    RawQuery::new("^x"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn phrase_slop_extraction() {
+        assert_eq!(
+            parse_no_fields("\"chicken thighs\"~3 -\"deep fry\"~0^2 \"a b\"~ \"c d\"~x"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("chicken thighs").phrase().slop(3),
+                    RawQuery::new("deep fry")
+                        .phrase()
+                        .slop(0)
+                        .boost(2.0)
+                        .must_not(),
+                    RawQuery::new("a b").phrase(),
+                    RawQuery::new("~"),
+                    RawQuery::new("c d").phrase(),
+                    RawQuery::new("~x"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn deeply_nested_garbage_is_fast() {
+        let input = "( ".repeat(5_000) + &"(a)".repeat(100);
+        let (remaining, parsed) = parse_query(input.as_str(), &false).unwrap();
+        assert_eq!("", remaining);
+        assert_eq!(5_100, parsed.len());
+    }
+
+    #[test]
+    fn deep_nesting_is_taken_literally() {
+        let nested = |depth: usize| "(".repeat(depth) + "a" + &")".repeat(depth);
+
+        let mut expected = term("a");
+        for _ in 0..MAX_NESTING {
+            expected = group(vec![expected]);
+        }
+        assert_eq!(
+            Ok(("", vec![expected])),
+            parse_query(nested(MAX_NESTING).as_str(), &false)
+        );
+
+        let too_deep = nested(MAX_NESTING + 1);
+        let (remaining, parsed) = parse_query(too_deep.as_str(), &false).unwrap();
+        assert_eq!("", remaining);
+        assert_eq!(term(&too_deep[..=MAX_NESTING + 1]), parsed[0]);
+        assert_eq!(MAX_NESTING + 2, parsed.len());
+
+        // Deep enough to overflow the stack if recursing all the way
+        let input = nested(50_000);
+        let (remaining, parsed) = parse_query(input.as_str(), &false).unwrap();
+        assert_eq!("", remaining);
+        assert_eq!(50_001, parsed.len());
+
+        // Nesting past the limit within a group
+        let input = format!("(b {})", nested(MAX_NESTING));
+        let (remaining, parsed) = parse_query(input.as_str(), &false).unwrap();
+        assert_eq!("", remaining);
+        assert_eq!(term("(b"), parsed[0]);
+    }
+
     use quickcheck::QuickCheck;
 
     #[test]
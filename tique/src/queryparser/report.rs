@@ -0,0 +1,63 @@
+use std::fmt;
+
+use tantivy::query::Query;
+
+use super::limits::LimitExceeded;
+
+/// The outcome of `QueryParser::parse_with_report`
+#[derive(Debug)]
+pub struct ParseReport {
+    /// The same query `QueryParser::parse` yields for the input
+    pub query: Option<Box<dyn Query>>,
+    /// Everything about the input that didn't go as one would expect,
+    /// in the order it appears
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Explains why (part of) a query may not match what the user wanted
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// Input that couldn't be parsed at all
+    IgnoredInput(String),
+    /// A word or phrase that the field analyzers turned into nothing,
+    /// like a stop word
+    NoTokens(String),
+    /// Something that looks like `field:text`, but with a field name
+    /// the parser doesn't know, so it was searched for as text
+    UnknownField {
+        /// The name before the `:`
+        field_name: String,
+        /// Everything that was searched for
+        input: String,
+    },
+    /// A word that appears in no document of any field it was searched in
+    UnknownTerm(String),
+    /// A comparison like `calories:<1.5` with bounds that don't fit the
+    /// field, so it was ignored
+    InvalidRange {
+        /// The field being compared against
+        field_name: String,
+    },
+    /// Part of the input was dropped (or a phrase shortened) to stay
+    /// within the limits configured via `QueryParser::set_limits`
+    LimitExceeded(LimitExceeded),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::IgnoredInput(input) => write!(f, "Ignored unparsed input: {}", input),
+            Diagnostic::NoTokens(input) => write!(f, "Nothing to search for in: {}", input),
+            Diagnostic::UnknownField { field_name, input } => write!(
+                f,
+                "Unknown field {}, searched for {} as text",
+                field_name, input
+            ),
+            Diagnostic::UnknownTerm(term) => write!(f, "No document contains: {}", term),
+            Diagnostic::InvalidRange { field_name } => {
+                write!(f, "Ignored invalid range for field {}", field_name)
+            }
+            Diagnostic::LimitExceeded(exceeded) => write!(f, "Truncated: {}", exceeded),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+const GROUP_SEP: char = ',';
+const MAPPING_SEP: &str = "=>";
+const COMMENT: char = '#';
+
+/// Words and phrases that should be searched for interchangeably
+///
+/// Entries are either groups of equivalent words, where searching for
+/// any one of them also searches for all the others, or one-way
+/// mappings, where searching for a word also searches for its
+/// replacements but not the other way around.
+///
+/// Attach it to a field with `QueryParser::set_synonyms`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Synonyms {
+    entries: Vec<(Vec<String>, Vec<String>)>,
+}
+
+impl Synonyms {
+    /// Create an empty synonym map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make every item in `words` a synonym of every other
+    ///
+    /// Items may have multiple words, like "green onion", in which
+    /// case they are searched as phrases.
+    pub fn add_equivalent<S: AsRef<str>>(&mut self, words: &[S]) {
+        let words = words
+            .iter()
+            .map(|word| word.as_ref().to_owned())
+            .collect::<Vec<_>>();
+        self.entries.push((words.clone(), words));
+    }
+
+    /// Make searches for any item in `from` also search for the ones
+    /// in `to`
+    pub fn add_mapping<S: AsRef<str>>(&mut self, from: &[S], to: &[S]) {
+        self.entries.push((
+            from.iter().map(|word| word.as_ref().to_owned()).collect(),
+            to.iter().map(|word| word.as_ref().to_owned()).collect(),
+        ));
+    }
+
+    /// Read synonyms from a file
+    ///
+    /// See `Synonyms::from_reader` for the expected format.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Read synonyms from text with one entry per line
+    ///
+    /// Lines like `cilantro, coriander` list equivalent words and lines
+    /// like `aubergine => eggplant` map the words on the left to the
+    /// ones on the right. Empty lines and everything after a `#` are
+    /// ignored:
+    ///
+    /// ```text
+    /// # Same thing, different names
+    /// scallion, green onion, spring onion
+    /// courgette, zucchini
+    /// aubergine, brinjal => eggplant
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will yield an `InvalidData` error for lines with an empty item
+    /// or with more than one `=>`
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut synonyms = Self::new();
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split(COMMENT).next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid synonyms at line {}: {}", idx + 1, line),
+                )
+            };
+
+            let mut sides = line.split(MAPPING_SEP);
+            let from = split_group(sides.next().unwrap_or("")).ok_or_else(invalid)?;
+
+            match (sides.next(), sides.next()) {
+                (None, _) => synonyms.add_equivalent(&from),
+                (Some(to), None) => {
+                    let to = split_group(to).ok_or_else(invalid)?;
+                    synonyms.add_mapping(&from, &to);
+                }
+                (Some(_), Some(_)) => return Err(invalid()),
+            }
+        }
+
+        Ok(synonyms)
+    }
+
+    /// Every word paired with the words it should also search for,
+    /// including itself for equivalent groups
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.entries
+            .iter()
+            .flat_map(|(from, to)| from.iter().map(move |word| (word.as_str(), to.as_slice())))
+    }
+}
+
+/// `None` when any item in the group is empty
+fn split_group(input: &str) -> Option<Vec<&str>> {
+    input
+        .split(GROUP_SEP)
+        .map(|item| {
+            let item = item.trim();
+            if item.is_empty() {
+                None
+            } else {
+                Some(item)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_synonym_files() -> io::Result<()> {
+        let text = "
+            # comment
+            cilantro, coriander
+
+            scallion, green onion # trailing comment
+            aubergine,brinjal => eggplant
+        ";
+
+        let mut expected = Synonyms::new();
+        expected.add_equivalent(&["cilantro", "coriander"]);
+        expected.add_equivalent(&["scallion", "green onion"]);
+        expected.add_mapping(&["aubergine", "brinjal"], &["eggplant"]);
+
+        assert_eq!(expected, Synonyms::from_reader(text.as_bytes())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for line in &["a,,b", "a => b => c", "=> b", "a =>", ","] {
+            let err = Synonyms::from_reader(line.as_bytes()).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+    }
+}
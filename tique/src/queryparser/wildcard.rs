@@ -0,0 +1,144 @@
+use std::{cmp::Reverse, collections::BTreeMap, str};
+
+use tantivy::{
+    query::{BooleanQuery, EmptyQuery, Occur, Query, TermQuery, Weight},
+    schema::{Field, IndexRecordOption},
+    Result, Searcher, Term,
+};
+
+pub(crate) const WILDCARD: char = '*';
+
+/// Matches every term in a field that fits a pattern where `*` stands
+/// for any sequence of characters, like `choco*` or `choc*late`
+///
+/// The pattern is expanded into (at most `max_expansions`) terms when
+/// creating the weight, favouring the ones that appear in the most
+/// documents, so that patterns like `a*` can't end up turning into a
+/// query with thousands of terms.
+#[derive(Debug, Clone)]
+pub(crate) struct WildcardQuery {
+    field: Field,
+    pattern: String,
+    max_expansions: usize,
+}
+
+impl WildcardQuery {
+    pub fn new(field: Field, pattern: String, max_expansions: usize) -> Self {
+        debug_assert!(pattern.contains(WILDCARD));
+        Self {
+            field,
+            pattern,
+            max_expansions,
+        }
+    }
+
+    fn expand(&self, searcher: &Searcher) -> Vec<Term> {
+        let prefix = self.pattern.split(WILDCARD).next().unwrap_or("");
+        let mut doc_freqs: BTreeMap<String, u64> = BTreeMap::new();
+
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(self.field);
+            let mut stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream();
+
+            while stream.advance() {
+                let key = stream.key();
+                if !key.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+
+                match str::from_utf8(key) {
+                    Ok(text) if matches(&self.pattern, text) => {
+                        *doc_freqs.entry(text.to_owned()).or_insert(0) +=
+                            u64::from(stream.value().doc_freq);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut expansions = doc_freqs.into_iter().collect::<Vec<_>>();
+        // Stable sort: ties are kept in term order
+        expansions.sort_by_key(|(_term, doc_freq)| Reverse(*doc_freq));
+        expansions.truncate(self.max_expansions);
+
+        expansions
+            .into_iter()
+            .map(|(term, _doc_freq)| Term::from_field_text(self.field, &term))
+            .collect()
+    }
+}
+
+impl Query for WildcardQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        let terms = self.expand(searcher);
+
+        if terms.is_empty() {
+            EmptyQuery.weight(searcher, scoring_enabled)
+        } else {
+            BooleanQuery::from(
+                terms
+                    .into_iter()
+                    .map(|term| {
+                        let query: Box<dyn Query> =
+                            Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                        (Occur::Should, query)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .weight(searcher, scoring_enabled)
+        }
+    }
+}
+
+/// Whether `text` fits `pattern`, with `*` matching any sequence of
+/// characters (including none)
+fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and of the text when it was seen
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == WILDCARD {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, star_t)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star + 1;
+            t = star_t + 1;
+            backtrack = Some((star, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == WILDCARD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matching() {
+        assert!(matches("choco*", "choco"));
+        assert!(matches("choco*", "chocolate"));
+        assert!(matches("choc*late", "chocolate"));
+        assert!(matches("c*o*e", "chocolate"));
+        assert!(matches("a**", "a"));
+        assert!(matches("*", ""));
+
+        assert!(!matches("choco*", "choc"));
+        assert!(!matches("choc*late", "chocolatey"));
+        assert!(!matches("c*x", "chocolate"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Spelling suggestions ("did you mean") built from your index.
+//!
+//! Words that can't be found in any of the configured fields are
+//! replaced by the most frequent word in the index that is just a
+//! few typos away. The distance tolerated grows with the length of
+//! the word: words shorter than 3 characters are never corrected,
+//! up to 5 characters a single edit is allowed and longer words may
+//! need up to 2 edits (limited by `Suggester::set_max_distance`).
+//!
+//! Suggestions come straight from the term dictionaries, so fields
+//! analyzed without stemming give better results: with a stemmer
+//! the suggestions are stems like "potato" for "potatos".
+//!
+//! # Examples
+//!
+//!```no_run
+//! # use tantivy::{Index, schema::Field, Result};
+//! # use tique::suggest::Suggester;
+//! # fn example(index: &Index, name: Field, ingredients: Field) -> Result<()> {
+//! let suggester = Suggester::new(&index, vec![name, ingredients])?;
+//!
+//! if let Some(suggestion) = suggester.suggest("bacn pancakes") {
+//!     println!("Did you mean: {}", suggestion);
+//! }
+//! # Ok(())
+//! # }
+//!```
+use std::{cmp::Reverse, collections::HashMap, str};
+
+use levenshtein_automata::LevenshteinAutomatonBuilder;
+use tantivy::{
+    schema::{Field, FieldType, Schema},
+    tokenizer::TextAnalyzer,
+    Index, IndexReader, Result, Searcher, TantivyError, Term,
+};
+
+const MAX_DISTANCE: u8 = 2;
+
+/// Suggests corrections for words that can't be found in the index
+pub struct Suggester {
+    reader: IndexReader,
+    field_tokenizers: Vec<(Field, TextAnalyzer)>,
+    max_distance: u8,
+    // One builder per allowed distance, transpositions costing 1
+    builders: Vec<LevenshteinAutomatonBuilder>,
+}
+
+impl Suggester {
+    /// Creates a new Suggester that takes its vocabulary from the
+    /// given index fields
+    ///
+    /// # Errors
+    ///
+    /// Will yield an error if `fields` is empty or if any of its fields
+    /// is not an indexed text field
+    pub fn new(index: &Index, fields: Vec<Field>) -> Result<Self> {
+        if fields.is_empty() {
+            return Err(TantivyError::InvalidArgument(
+                "Suggester needs at least one field".to_owned(),
+            ));
+        }
+
+        let schema = index.schema();
+        let mut field_tokenizers = Vec::with_capacity(fields.len());
+
+        for field in fields {
+            if field_is_valid(&schema, field) {
+                field_tokenizers.push((field, index.tokenizer_for_field(field)?));
+            } else {
+                return Err(TantivyError::SchemaError(format!(
+                    "Field '{}' is not an indexed text field",
+                    schema.get_field_name(field)
+                )));
+            }
+        }
+
+        Ok(Self {
+            reader: index.reader()?,
+            field_tokenizers,
+            max_distance: MAX_DISTANCE,
+            builders: (1..=MAX_DISTANCE)
+                .map(|distance| LevenshteinAutomatonBuilder::new(distance, true))
+                .collect(),
+        })
+    }
+
+    /// Limit how many edits a correction may be away from the word
+    /// being corrected. Defaults to 2, `0` disables suggestions.
+    ///
+    /// Panics when `distance` is greater than 2.
+    pub fn set_max_distance(&mut self, distance: u8) {
+        assert!(distance <= MAX_DISTANCE, "distance must be at most 2");
+        self.max_distance = distance;
+    }
+
+    /// Rewrites `input` with every unknown word replaced by its best
+    /// correction
+    ///
+    /// Words are found using the analyzer of the first field and
+    /// everything else in the input is kept as is. Yields `None` when
+    /// there's nothing to correct.
+    pub fn suggest(&self, input: &str) -> Option<String> {
+        let searcher = self.reader.searcher();
+
+        let mut unknown = Vec::new();
+        let mut stream = self.field_tokenizers[0].1.token_stream(input);
+        stream.process(&mut |token| {
+            if !self.is_known(&searcher, &token.text) {
+                unknown.push((token.offset_from, token.offset_to, token.text.clone()));
+            }
+        });
+
+        if unknown.is_empty() {
+            return None;
+        }
+
+        let words = unknown
+            .iter()
+            .map(|(_from, _to, word)| word.as_str())
+            .collect::<Vec<_>>();
+        let nearby = self.nearby(&searcher, &words);
+
+        let mut output = input.to_owned();
+        let mut changed = false;
+        // Replacing from the end keeps the offsets before it valid
+        for ((from, to, _word), candidates) in unknown.iter().zip(nearby).rev() {
+            if let Some((correction, _doc_freq)) = rank(candidates).into_iter().next() {
+                output.replace_range(from..to, &correction);
+                changed = true;
+            }
+        }
+
+        if changed {
+            Some(output)
+        } else {
+            None
+        }
+    }
+
+    /// Finds at most `limit` words that are close to `word`, paired
+    /// with how many documents contain them, most frequent first
+    pub fn candidates(&self, word: &str, limit: usize) -> Vec<(String, u64)> {
+        let searcher = self.reader.searcher();
+        let word = word.to_lowercase();
+
+        let mut candidates = rank(self.nearby(&searcher, &[word.as_str()]).remove(0));
+        candidates.truncate(limit);
+        candidates
+    }
+
+    fn is_known(&self, searcher: &Searcher, word: &str) -> bool {
+        self.field_tokenizers.iter().any(|(field, analyzer)| {
+            let mut found = false;
+            analyzer.token_stream(word).process(&mut |token| {
+                found |= searcher.doc_freq(&Term::from_field_text(*field, &token.text)) > 0;
+            });
+            found
+        })
+    }
+
+    /// Walks the term dictionaries with a Levenshtein automaton for
+    /// each of `words`, collecting the words close enough to it along
+    /// with their total doc_freq
+    fn nearby(&self, searcher: &Searcher, words: &[&str]) -> Vec<HashMap<String, u64>> {
+        words
+            .iter()
+            .map(|word| {
+                let mut found = HashMap::new();
+
+                let max_distance = self
+                    .max_distance
+                    .min(allowed_distance(word.chars().count()));
+                if max_distance == 0 {
+                    return found;
+                }
+
+                let dfa = self.builders[usize::from(max_distance) - 1].build_dfa(word);
+                for (field, _analyzer) in &self.field_tokenizers {
+                    for segment_reader in searcher.segment_readers() {
+                        let inverted_index = segment_reader.inverted_index(*field);
+                        let mut stream = inverted_index.terms().search(&dfa).into_stream();
+
+                        while stream.advance() {
+                            let text = match str::from_utf8(stream.key()) {
+                                Ok(text) => text,
+                                Err(_) => continue,
+                            };
+
+                            if text != *word {
+                                *found.entry(text.to_owned()).or_insert(0) +=
+                                    u64::from(stream.value().doc_freq);
+                            }
+                        }
+                    }
+                }
+
+                found
+            })
+            .collect()
+    }
+}
+
+/// Most frequent first, ties broken alphabetically
+fn rank(candidates: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut ranked = candidates.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|(a, a_freq), (b, b_freq)| (Reverse(a_freq), a).cmp(&(Reverse(b_freq), b)));
+    ranked
+}
+
+/// How many edits a word with `len` characters may need
+fn allowed_distance(len: usize) -> u8 {
+    match len {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+fn field_is_valid(schema: &Schema, field: Field) -> bool {
+    if let FieldType::Str(opts) = schema.get_field_entry(field).field_type() {
+        opts.get_indexing_options().is_some()
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use levenshtein_automata::Distance;
+    use tantivy::{
+        doc,
+        schema::{SchemaBuilder, STORED, TEXT},
+    };
+
+    fn dist(a: &str, b: &str) -> Option<u8> {
+        match LevenshteinAutomatonBuilder::new(2, true)
+            .build_dfa(a)
+            .eval(b)
+        {
+            Distance::Exact(distance) => Some(distance),
+            Distance::AtLeast(_) => None,
+        }
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(Some(0), dist("bacon", "bacon"));
+        assert_eq!(Some(1), dist("bacn", "bacon"));
+        assert_eq!(Some(1), dist("bcaon", "bacon"));
+        assert_eq!(Some(1), dist("bacom", "bacon"));
+        assert_eq!(Some(2), dist("backon", "bacn"));
+        assert_eq!(Some(2), dist("", "ab"));
+        assert_eq!(None, dist("bacon", "cheese"));
+        assert_eq!(None, dist("a", "abcd"));
+    }
+
+    #[test]
+    fn suggests_frequent_nearby_words() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let ingredients = builder.add_text_field("ingredients", TEXT);
+        let id = builder.add_u64_field("id", STORED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(name => "Bacon Pancakes", ingredients => "bacon flour eggs"));
+        writer.add_document(doc!(name => "Beacon Rolls", ingredients => "bacon bread"));
+        writer.add_document(doc!(name => "Fried Eggs", ingredients => "eggs butter"));
+        writer.commit()?;
+
+        assert!(Suggester::new(&index, vec![name, id]).is_err());
+        assert!(Suggester::new(&index, Vec::new()).is_err());
+
+        let mut suggester = Suggester::new(&index, vec![name, ingredients])?;
+
+        assert_eq!(
+            vec![("bacon".to_owned(), 3), ("beacon".to_owned(), 1)],
+            suggester.candidates("Baecon", 5)
+        );
+        assert_eq!(
+            vec![("bacon".to_owned(), 3)],
+            suggester.candidates("baecon", 1)
+        );
+        // Shorter words tolerate fewer edits
+        assert_eq!(
+            vec![("bacon".to_owned(), 3)],
+            suggester.candidates("bacn", 5)
+        );
+
+        assert_eq!(
+            Some("+bacon pancakes -eggs".to_owned()),
+            suggester.suggest("+Bacn pancakes -egs")
+        );
+        // Known and short words are left alone
+        assert_eq!(None, suggester.suggest("bacon eg"));
+        assert_eq!(None, suggester.suggest("zzzzzz"));
+
+        suggester.set_max_distance(0);
+        assert_eq!(None, suggester.suggest("bacn"));
+
+        Ok(())
+    }
+}
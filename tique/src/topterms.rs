@@ -63,16 +63,43 @@
 //! # }
 //!```
 //!
+//! ## Weighing Fields
+//!
+//! Not every field describes a document equally well. Words from a
+//! field can be made more (or less) important, the number of keywords
+//! taken from it capped and the queries made from them grouped by
+//! field, so that documents are judged by their best matching field:
+//!
+//!```no_run
+//! # use tantivy::{DocAddress, Index, Searcher, collector::TopDocs, schema::Field, Result};
+//! # use tique::topterms::TopTerms;
+//! # fn example(index: &Index, body: Field, title: Field,
+//! #   doc_address: DocAddress, searcher: &Searcher) -> Result<()> {
+//! let mut topterms = TopTerms::new(&index, vec![body, title])?;
+//! topterms.set_weight(title, 2.0);
+//! topterms.set_limit(body, Some(5));
+//! topterms.set_length_normalization(true);
+//!
+//! let keywords = topterms.extract_from_doc(10, doc_address);
+//! let nearest_neighbors =
+//!      searcher.search(&keywords.into_dismax_query(1.0, 0.1), &TopDocs::with_limit(10))?;
+//! # Ok(())
+//! # }
+//!```
+//!
 use std::{collections::HashMap, str};
 
 use tantivy::{
-    query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery},
+    query::{BooleanQuery, Occur, Query, TermQuery},
     schema::{Field, FieldType, IndexRecordOption, Schema},
     tokenizer::TextAnalyzer,
     DocAddress, DocSet, Index, IndexReader, Postings, Result, Searcher, SkipResult, Term,
 };
 
-use crate::conditional_collector::topk::{DescendingTopK, TopK};
+use crate::{
+    conditional_collector::topk::{DescendingTopK, TopK},
+    dismax::{BoostedQuery, DisMaxQuery},
+};
 
 // Source: Copy-pasta from tantivy::query::bm25::idf
 fn idf(doc_freq: u64, doc_count: u64) -> f32 {
@@ -80,10 +107,34 @@ fn idf(doc_freq: u64, doc_count: u64) -> f32 {
     (1f32 + x).ln()
 }
 
+// Same parameters as tantivy's BM25
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// The term frequency component of BM25
+fn normalized_tf(tf: u32, field_length: u32, average_length: f32) -> f32 {
+    let tf = tf as f32;
+    let norm = if average_length > 0.0 {
+        K1 * (1.0 - B + B * field_length as f32 / average_length)
+    } else {
+        K1
+    };
+
+    tf * (K1 + 1.0) / (tf + norm)
+}
+
 /// TopTerms extracts the most relevant Keywords from your index
 pub struct TopTerms {
     reader: IndexReader,
-    field_tokenizers: Vec<(Field, TextAnalyzer)>,
+    fields: Vec<FieldState>,
+    normalize_length: bool,
+}
+
+struct FieldState {
+    field: Field,
+    tokenizer: TextAnalyzer,
+    weight: f32,
+    limit: Option<usize>,
 }
 
 /// Allows tuning the algorithm to pick the top keywords
@@ -124,12 +175,17 @@ impl TopTerms {
     /// Will yield an error if the provided fields are unknown or if they
     /// are not `tantivy::schema::TEXT`
     pub fn new(index: &Index, fields: Vec<Field>) -> Result<Self> {
-        let mut field_tokenizers = Vec::new();
+        let mut states = Vec::new();
 
         for field in fields {
             if field_is_valid(&index.schema(), field) {
-                let tok = index.tokenizer_for_field(field)?;
-                field_tokenizers.push((field, tok));
+                let tokenizer = index.tokenizer_for_field(field)?;
+                states.push(FieldState {
+                    field,
+                    tokenizer,
+                    weight: 1.0,
+                    limit: None,
+                });
             } else {
                 let msg = format!(
                     "Field '{}' is not a text field with frequencies (TEXT)",
@@ -141,10 +197,41 @@ impl TopTerms {
 
         Ok(Self {
             reader: index.reader()?,
-            field_tokenizers,
+            fields: states,
+            normalize_length: false,
         })
     }
 
+    /// Change how important the terms of a field are
+    ///
+    /// The score of every term found in `field` gets multiplied by
+    /// `weight`, so a field with weight `2.0` wins over the others
+    /// unless they have terms scoring twice as high. Defaults to `1.0`.
+    pub fn set_weight(&mut self, field: Field, weight: f32) {
+        if let Some(state) = self.fields.iter_mut().find(|state| state.field == field) {
+            state.weight = weight;
+        }
+    }
+
+    /// Pick at most `limit` keywords from a field, regardless of how
+    /// many are asked for in total. Unlimited by default.
+    pub fn set_limit(&mut self, field: Field, limit: Option<usize>) {
+        if let Some(state) = self.fields.iter_mut().find(|state| state.field == field) {
+            state.limit = limit;
+        }
+    }
+
+    /// Account for the length of each field when scoring terms
+    ///
+    /// By default a term scores proportionally to how often it shows
+    /// up in the input. When enabled, its frequency is saturated and
+    /// weighed against the average length of the field in the index
+    /// the same way BM25 does, so that long fields don't drown short
+    /// ones out. Disabled by default.
+    pub fn set_length_normalization(&mut self, enabled: bool) {
+        self.normalize_length = enabled;
+    }
+
     /// Extracts the `limit` most relevant terms from the input
     pub fn extract(&self, limit: usize, input: &str) -> Keywords {
         self.extract_filtered(limit, input, &())
@@ -155,6 +242,14 @@ impl TopTerms {
         self.extract_filtered_from_doc(limit, addr, &())
     }
 
+    /// Extracts the `limit` most relevant terms from an input with a
+    /// separate text for each field, like a document that hasn't been
+    /// indexed. A field may appear many times and texts for fields
+    /// this TopTerms doesn't know about are ignored
+    pub fn extract_from_fields(&self, limit: usize, inputs: &[(Field, &str)]) -> Keywords {
+        self.extract_filtered_from_fields(limit, inputs, &())
+    }
+
     /// Same as `extract`, but with support inspect/filter the terms as
     /// they are being picked.
     pub fn extract_filtered<F: KeywordAcceptor>(
@@ -163,23 +258,28 @@ impl TopTerms {
         input: &str,
         acceptor: &F,
     ) -> Keywords {
-        let searcher = self.reader.searcher();
-        let num_docs = searcher.num_docs();
-
-        let mut keywords = DescendingTopK::new(limit);
-
-        for (field, tokenizer) in &self.field_tokenizers {
-            for (term, tf) in termfreq(&input, *field, tokenizer) {
-                let doc_freq = searcher.doc_freq(&term);
+        self.pick(limit, acceptor, |_searcher, state| {
+            field_input_termfreq(&[input], state)
+        })
+    }
 
-                if doc_freq > 0 && acceptor.accept(&term, tf, doc_freq, num_docs) {
-                    let score = tf as f32 * idf(doc_freq, num_docs);
-                    keywords.visit(term, score);
-                }
-            }
-        }
+    /// Same as `extract_from_fields`, but with support inspect/filter
+    /// the terms as they are being picked.
+    pub fn extract_filtered_from_fields<F: KeywordAcceptor>(
+        &self,
+        limit: usize,
+        inputs: &[(Field, &str)],
+        acceptor: &F,
+    ) -> Keywords {
+        self.pick(limit, acceptor, |_searcher, state| {
+            let texts = inputs
+                .iter()
+                .filter(|(field, _text)| *field == state.field)
+                .map(|(_field, text)| *text)
+                .collect::<Vec<_>>();
 
-        keywords.into()
+            field_input_termfreq(&texts, state)
+        })
     }
 
     /// Same as `extract_from_doc`, but with support inspect/filter the
@@ -190,19 +290,60 @@ impl TopTerms {
         addr: DocAddress,
         acceptor: &F,
     ) -> Keywords {
+        self.pick(limit, acceptor, |searcher, state| {
+            let mut termfreqs = Vec::new();
+            termfreq_for_doc(searcher, state.field, addr, |term, term_freq| {
+                termfreqs.push((term, term_freq));
+            });
+
+            let DocAddress(seg_id, doc_id) = addr;
+            let field_length = searcher
+                .segment_reader(seg_id)
+                .get_fieldnorms_reader(state.field)
+                .fieldnorm(doc_id);
+
+            (termfreqs, field_length)
+        })
+    }
+
+    /// Picks the top keywords out of the terms (and the length of
+    /// the input) that `termfreqs` finds for each field
+    fn pick<F, T>(&self, limit: usize, acceptor: &F, mut termfreqs: T) -> Keywords
+    where
+        F: KeywordAcceptor,
+        T: FnMut(&Searcher, &FieldState) -> (Vec<(Term, u32)>, u32),
+    {
         let searcher = self.reader.searcher();
         let num_docs = searcher.num_docs();
 
         let mut keywords = DescendingTopK::new(limit);
 
-        for (field, _tokenizer) in &self.field_tokenizers {
-            termfreq_for_doc(&searcher, *field, addr, |term, term_freq| {
+        for state in &self.fields {
+            let (terms, field_length) = termfreqs(&searcher, state);
+            let average_length = if self.normalize_length {
+                Some(average_field_length(&searcher, state.field))
+            } else {
+                None
+            };
+
+            let mut field_keywords =
+                DescendingTopK::new(state.limit.map_or(limit, |l| l.min(limit)));
+
+            for (term, tf) in terms {
                 let doc_freq = searcher.doc_freq(&term);
-                if acceptor.accept(&term, term_freq, doc_freq, num_docs) {
-                    let score = term_freq as f32 * idf(doc_freq, num_docs);
-                    keywords.visit(term, score);
+
+                if doc_freq > 0 && acceptor.accept(&term, tf, doc_freq, num_docs) {
+                    let tf = average_length.map_or(tf as f32, |average_length| {
+                        normalized_tf(tf, field_length, average_length)
+                    });
+                    let score = state.weight * tf * idf(doc_freq, num_docs);
+                    field_keywords.visit(term, score);
                 }
-            });
+            }
+
+            for (term, score) in field_keywords.into_vec() {
+                keywords.visit(term, score);
+            }
         }
 
         keywords.into()
@@ -234,12 +375,48 @@ impl Keywords {
         for (term, score) in self.0 {
             let boost = boost_factor * (score / max_score);
             let tq = Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
-            clauses.push((Occur::Should, Box::new(BoostQuery::new(tq, boost))));
+            clauses.push((Occur::Should, Box::new(BoostedQuery::new(tq, boost))));
         }
 
         BooleanQuery::from(clauses)
     }
 
+    /// Same as `into_boosted_query`, but with the terms grouped by
+    /// field: the terms of each field make up a boolean query and
+    /// a document scores as its best matching field plus the other
+    /// fields' scores multiplied by `tiebreaker`. See `DisMaxQuery`
+    pub fn into_dismax_query(self, boost_factor: f32, tiebreaker: f32) -> DisMaxQuery {
+        let max_score = self.0.first().map_or(0.0, |(_term, score)| *score);
+
+        let mut fields: Vec<(Field, Vec<Box<dyn Query>>)> = Vec::new();
+
+        for (term, score) in self.0 {
+            let field = term.field();
+            let boost = boost_factor * (score / max_score);
+            let tq = Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+            let clause = Box::new(BoostedQuery::new(tq, boost));
+
+            match fields.iter_mut().find(|(known, _clauses)| *known == field) {
+                Some((_field, clauses)) => clauses.push(clause),
+                None => fields.push((field, vec![clause])),
+            }
+        }
+
+        DisMaxQuery::new(
+            fields
+                .into_iter()
+                .map(|(_field, clauses)| -> Box<dyn Query> {
+                    let clauses = clauses
+                        .into_iter()
+                        .map(|clause| (Occur::Should, clause))
+                        .collect::<Vec<_>>();
+                    Box::new(BooleanQuery::from(clauses))
+                })
+                .collect(),
+            tiebreaker,
+        )
+    }
+
     /// Iterates over the terms of this keywords set, more relevant
     /// terms appear first
     pub fn terms(&self) -> impl Iterator<Item = &Term> {
@@ -282,6 +459,42 @@ fn termfreq(input: &str, field: Field, tokenizer: &TextAnalyzer) -> HashMap<Term
     termfreq
 }
 
+/// The frequencies of the terms found in `texts` when analyzed as
+/// the field of `state`, along with how many tokens there are
+fn field_input_termfreq(texts: &[&str], state: &FieldState) -> (Vec<(Term, u32)>, u32) {
+    let mut merged: HashMap<Term, u32> = HashMap::new();
+
+    for text in texts {
+        for (term, tf) in termfreq(text, state.field, &state.tokenizer) {
+            *merged.entry(term).or_insert(0) += tf;
+        }
+    }
+
+    let field_length = merged.values().sum();
+    (merged.into_iter().collect(), field_length)
+}
+
+/// The average number of tokens the field has in the documents of
+/// the index
+fn average_field_length(searcher: &Searcher, field: Field) -> f32 {
+    let (num_tokens, num_docs) =
+        searcher
+            .segment_readers()
+            .iter()
+            .fold((0, 0), |(num_tokens, num_docs), reader| {
+                (
+                    num_tokens + reader.inverted_index(field).total_num_tokens(),
+                    num_docs + u64::from(reader.max_doc()),
+                )
+            });
+
+    if num_docs == 0 {
+        0.0
+    } else {
+        num_tokens as f32 / num_docs as f32
+    }
+}
+
 fn termfreq_for_doc<F>(searcher: &Searcher, field: Field, doc: DocAddress, mut consumer: F)
 where
     F: FnMut(Term, u32),
@@ -383,6 +596,104 @@ mod tests {
         assert!(field_is_valid(&schema, valid));
     }
 
+    #[test]
+    fn length_normalization() {
+        // Saturates
+        assert!(normalized_tf(1, 10, 10.0) < normalized_tf(2, 10, 10.0));
+        assert!(normalized_tf(100, 10, 10.0) < K1 + 1.0);
+        // Favours shorter fields
+        assert!(normalized_tf(2, 5, 10.0) > normalized_tf(2, 10, 10.0));
+        assert!(normalized_tf(2, 10, 10.0) > normalized_tf(2, 20, 10.0));
+        // An empty index tells nothing about lengths
+        assert_eq!(normalized_tf(2, 10, 0.0), normalized_tf(2, 1, 0.0));
+    }
+
+    #[test]
+    fn per_field_tuning() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+
+        let name = builder.add_text_field("name", TEXT);
+        let instructions = builder.add_text_field("instructions", TEXT);
+
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(
+            name => "bacon pancakes",
+            instructions => "fry the bacon then the pancakes"
+        ));
+        writer.add_document(doc!(
+            name => "cheese toast",
+            instructions => "toast the bread and melt the cheese on top"
+        ));
+        writer.add_document(doc!(
+            name => "tomato salad",
+            instructions => "slice the tomato and season the salad"
+        ));
+        writer.commit()?;
+
+        let inputs = [
+            (name, "bacon toast"),
+            (instructions, "fry the bacon, toast the bread"),
+            (instructions, "then fry the eggs"),
+        ];
+
+        let fields_of = |keywords: Keywords| {
+            keywords
+                .into_sorted_vec()
+                .into_iter()
+                .map(|(term, _score)| term.field())
+                .collect::<Vec<_>>()
+        };
+
+        let mut topterms = TopTerms::new(&index, vec![name, instructions])?;
+
+        // "fry" shows up twice in the instructions
+        let keywords = topterms.extract_from_fields(1, &inputs);
+        assert_eq!(vec![instructions], fields_of(keywords.clone()));
+        assert_eq!("fry", keywords.terms().next().unwrap().text());
+
+        topterms.set_weight(name, 3.0);
+        assert_eq!(
+            vec![name],
+            fields_of(topterms.extract_from_fields(1, &inputs))
+        );
+
+        topterms.set_limit(name, Some(1));
+        let mut fields = fields_of(topterms.extract_from_fields(10, &inputs));
+        assert_eq!(Some(name), fields.first().copied());
+        fields.retain(|field| *field == name);
+        assert_eq!(vec![name], fields);
+
+        topterms.set_limit(name, Some(0));
+        assert!(!fields_of(topterms.extract_from_fields(10, &inputs)).contains(&name));
+
+        // Input that is read from the index and extracted from
+        // the same text yield the same keywords
+        topterms.set_limit(name, None);
+        topterms.set_length_normalization(true);
+        let from_doc = topterms.extract_from_doc(10, DocAddress(0, 1));
+        let from_fields = topterms.extract_from_fields(
+            10,
+            &[
+                (name, "cheese toast"),
+                (instructions, "toast the bread and melt the cheese on top"),
+            ],
+        );
+        assert_eq!(from_doc.into_sorted_vec(), from_fields.into_sorted_vec());
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+        let query = topterms
+            .extract_from_doc(4, DocAddress(0, 0))
+            .into_dismax_query(1.0, 0.1);
+
+        let found = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(3))?;
+        assert_eq!(Some(DocAddress(0, 0)), found.first().map(|x| x.1));
+
+        Ok(())
+    }
+
     #[test]
     fn topterms_integration() -> Result<()> {
         let mut builder = SchemaBuilder::new();